@@ -32,7 +32,7 @@ impl ConnectionOut {
         bincode::serialize_into(&mut self.buffer, msg).unwrap();
 
         let len = (self.buffer.len() - 4) as u32;
-        (&mut self.buffer[0..4]).copy_from_slice(&len.to_be_bytes());
+        self.buffer[0..4].copy_from_slice(&len.to_be_bytes());
 
         self.stream.write_all(&self.buffer).await
     }
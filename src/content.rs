@@ -0,0 +1,463 @@
+//! Definitions of grounds, terrain, items, creatures, recipes, level colours and PvP zones, loaded from
+//! `data/content.txt` so new content does not need code changes. See that file for the format.
+//!
+//! The host loads the file and sends the definitions to every joining client, so all players
+//! agree on them. The kind types in the world are indices into these tables.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+use crate::world::{Tile, GroundKind, TerrainKind, ItemKind};
+use crate::world::equipment::EquipSlot;
+use crate::world::building::Placement;
+use crate::world::creatures::{CreatureKind, Reaction};
+use crate::world::crafting::Recipe;
+use crate::world::combat::PvpZone;
+use crate::geom;
+use crate::level_loader;
+
+pub const CONTENT_PATH: &str = "data/content.txt";
+/// Recipes are picked by letter when crafting, so there can be no more than there are letters.
+pub const MAX_RECIPES: usize = 26;
+
+/// A colour as red, green and blue from 0 to 5, like the terminal's colour cube.
+pub type Color = (u8, u8, u8);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroundDef {
+    pub name: String,
+    pub glyph: char,
+    pub fg: Color,
+    pub bg: Option<Color>,
+    pub passable: bool,
+    /// Creatures that may spawn on the ground, with their relative chances.
+    pub spawns: Vec<(CreatureKind, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerrainDef {
+    pub name: String,
+    pub glyph: char,
+    pub fg: Color,
+    /// Drawn over the ground's background if missing.
+    pub bg: Option<Color>,
+    pub passable: bool,
+    pub opaque: bool,
+    /// `None` for terrain that cannot be broken.
+    pub hit_points: Option<u32>,
+    pub drops: Option<ItemKind>,
+    /// Ground left behind when the terrain is broken.
+    pub leaves: Option<GroundKind>,
+    /// Bare hands cannot break the terrain.
+    pub needs_tool: bool,
+    pub light: Option<i32>,
+    /// Leads to the entrance at the same place on the neighbouring layer.
+    pub entrance: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDef {
+    pub name: String,
+    pub stack: usize,
+    pub weight: u32,
+    pub slot: Option<EquipSlot>,
+    pub durability: Option<u32>,
+    pub damage: i64,
+    pub defense: i64,
+    /// How hard the item hits particular terrain; any other terrain is hit as with bare hands.
+    pub power: Vec<(TerrainKind, u32)>,
+    pub food: Option<i64>,
+    pub light: Option<i32>,
+    pub places: Option<Placement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatureDef {
+    pub name: String,
+    pub glyph: char,
+    pub fg: Color,
+    pub hp: i64,
+    pub damage: i64,
+    pub sight: i32,
+    pub think_delay: u64,
+    pub loot: Vec<(ItemKind, usize)>,
+    pub reaction: Reaction,
+}
+
+/// Kinds the world generator builds its landscape from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Palette {
+    pub grass: GroundKind,
+    pub rock: GroundKind,
+    pub water: GroundKind,
+    pub tree: TerrainKind,
+    pub cliff: TerrainKind,
+    pub entrance: TerrainKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Content {
+    pub grounds: Vec<GroundDef>,
+    pub terrain: Vec<TerrainDef>,
+    pub items: Vec<ItemDef>,
+    pub creatures: Vec<CreatureDef>,
+    pub recipes: Vec<Recipe>,
+    /// The surface and underground tiles for each colour in the level image.
+    pub level_colors: HashMap<(u8, u8, u8), (Tile, Tile)>,
+    pub palette: Palette,
+    /// Areas where the server's PvP setting is overridden, in order of precedence.
+    pub pvp_zones: Vec<PvpZone>,
+}
+
+static CONTENT: OnceLock<Content> = OnceLock::new();
+
+/// The loaded definitions. Panics if none have been loaded or installed yet.
+pub fn content() -> &'static Content {
+    CONTENT.get().expect("Content definitions used before they were loaded.")
+}
+
+/// Make `content` the definitions used by this process. Only the first call has any effect.
+pub fn install(content: Content) {
+    let _ = CONTENT.set(content);
+}
+
+/// Load and validate the definitions at `path`, check the level image against them, then install them.
+pub fn load(path: &str) -> Result<(), ContentError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| ContentError { path: path.to_string(), line: None, message: err.to_string() })?;
+    let content = parse(&text)
+        .map_err(|(line, message)| ContentError { path: path.to_string(), line, message })?;
+    level_loader::check_level(&content.level_colors)
+        .map_err(|message| ContentError { path: path.to_string(), line: None, message })?;
+    install(content);
+    Ok(())
+}
+
+/// A problem with the content file, pointing at the offending line where there is one.
+#[derive(Debug)]
+pub struct ContentError {
+    pub path: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path, line, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+impl Error for ContentError {}
+
+/// An error message and the line it applies to, if any.
+type ParseError = (Option<usize>, String);
+
+/// One `[category id]` block and its `key = value` lines.
+struct Section<'a> {
+    category: &'a str,
+    id: &'a str,
+    line: usize,
+    fields: Vec<(&'a str, &'a str, usize)>,
+}
+
+impl<'a> Section<'a> {
+    fn describe(&self) -> String {
+        format!("{} `{}`", self.category, self.id)
+    }
+    /// Remove and return a field, so that whatever is left over at the end is unknown.
+    fn take(&mut self, key: &str) -> Option<(&'a str, usize)> {
+        let ix = self.fields.iter().position(|(k, _, _)| *k == key)?;
+        let (_, value, line) = self.fields.remove(ix);
+        Some((value, line))
+    }
+    fn finish(&self) -> Result<(), ParseError> {
+        match self.fields.first() {
+            Some((key, _, line)) => Err((Some(*line), format!("unknown key `{}` in {}", key, self.describe()))),
+            None => Ok(()),
+        }
+    }
+    fn name(&mut self) -> String {
+        match self.take("name") {
+            Some((name, _)) => name.to_string(),
+            None => self.id.replace('_', " "),
+        }
+    }
+    fn required(&mut self, key: &str) -> Result<(&'a str, usize), ParseError> {
+        self.take(key).ok_or_else(|| (Some(self.line), format!("{} is missing `{}`", self.describe(), key)))
+    }
+}
+
+fn split_sections(text: &str) -> Result<Vec<Section<'_>>, ParseError> {
+    let mut sections: Vec<Section> = Vec::new();
+    for (ix, line) in text.lines().enumerate() {
+        let line_no = ix + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.strip_suffix(']')
+                .ok_or_else(|| (Some(line_no), "section header is missing `]`".to_string()))?;
+            let (category, id) = header.trim().split_once(' ')
+                .ok_or_else(|| (Some(line_no), format!("section `{}` needs a category and an id", header)))?;
+            sections.push(Section { category, id: id.trim(), line: line_no, fields: Vec::new() });
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| (Some(line_no), "expected `key = value` or a `[category id]` header".to_string()))?;
+        let section = sections.last_mut()
+            .ok_or_else(|| (Some(line_no), "field outside of any section".to_string()))?;
+        let key = key.trim();
+        if section.fields.iter().any(|(k, _, _)| *k == key) {
+            return Err((Some(line_no), format!("`{}` is given twice in {}", key, section.describe())));
+        }
+        section.fields.push((key, value.trim(), line_no));
+    }
+    Ok(sections)
+}
+
+/// Ids of every definition in one category, mapped to their index.
+struct Ids<'a> {
+    category: &'static str,
+    ids: HashMap<&'a str, u16>,
+}
+
+impl<'a> Ids<'a> {
+    fn collect(sections: &[Section<'a>], category: &'static str) -> Result<Ids<'a>, ParseError> {
+        let mut ids = HashMap::new();
+        for section in sections.iter().filter(|section| section.category == category) {
+            let ix = ids.len() as u16;
+            if ids.insert(section.id, ix).is_some() {
+                return Err((Some(section.line), format!("{} is defined twice", section.describe())));
+            }
+        }
+        Ok(Ids { category, ids })
+    }
+    fn get(&self, id: &str, line: usize) -> Result<u16, ParseError> {
+        self.ids.get(id).copied()
+            .ok_or_else(|| (Some(line), format!("there is no {} `{}`", self.category, id)))
+    }
+    fn require(&self, id: &str) -> Result<u16, ParseError> {
+        self.ids.get(id).copied()
+            .ok_or_else(|| (None, format!("the world generator needs a {} called `{}`", self.category, id)))
+    }
+}
+
+fn number<T: FromStr>(value: &str, line: usize) -> Result<T, ParseError> {
+    value.parse().map_err(|_| (Some(line), format!("`{}` is not a valid number here", value)))
+}
+
+/// A number above zero, for sizes and durations where zero would break the game.
+fn positive<T: FromStr + Default + PartialOrd>(value: &str, line: usize) -> Result<T, ParseError> {
+    let n = number(value, line)?;
+    if n <= T::default() {
+        return Err((Some(line), format!("`{}` has to be more than 0", value)));
+    }
+    Ok(n)
+}
+
+fn flag(value: &str, line: usize) -> Result<bool, ParseError> {
+    match value {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err((Some(line), format!("expected `yes` or `no`, found `{}`", value))),
+    }
+}
+
+fn glyph(value: &str, line: usize) -> Result<char, ParseError> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err((Some(line), format!("a glyph is a single character, found `{}`", value))),
+    }
+}
+
+fn color(value: &str, line: usize) -> Result<Color, ParseError> {
+    let parts = value.split_whitespace()
+        .map(|part| part.parse::<u8>().ok().filter(|&level| level <= 5))
+        .collect::<Option<Vec<_>>>();
+    match parts.as_deref() {
+        Some(&[r, g, b]) => Ok((r, g, b)),
+        _ => Err((Some(line), format!("a colour is three numbers from 0 to 5, found `{}`", value))),
+    }
+}
+
+/// A position on the map as `x y`.
+fn point(value: &str, line: usize) -> Result<(i32, i32), ParseError> {
+    let parts = value.split_whitespace()
+        .map(|part| part.parse::<i32>().ok())
+        .collect::<Option<Vec<_>>>();
+    match parts.as_deref() {
+        Some(&[x, y]) => Ok((x, y)),
+        _ => Err((Some(line), format!("a position is two whole numbers, found `{}`", value))),
+    }
+}
+
+/// A comma separated list of `id number` pairs.
+fn counted_list<T: FromStr>(value: &str, line: usize, ids: &Ids) -> Result<Vec<(u16, T)>, ParseError> {
+    value.split(',')
+        .map(|entry| {
+            let entry = entry.trim();
+            let (id, count) = entry.split_once(' ')
+                .ok_or_else(|| (Some(line), format!("expected an id and a number, found `{}`", entry)))?;
+            Ok((ids.get(id, line)?, number(count.trim(), line)?))
+        })
+        .collect()
+}
+
+/// A ground id optionally followed by a terrain id.
+fn tile(value: &str, line: usize, grounds: &Ids, terrain: &Ids) -> Result<Tile, ParseError> {
+    let mut parts = value.split_whitespace();
+    let ground = parts.next()
+        .ok_or_else(|| (Some(line), "expected a ground and an optional terrain".to_string()))?;
+    let tile = Tile {
+        ground: Some(GroundKind(grounds.get(ground, line)?)),
+        terrain: parts.next().map(|id| terrain.get(id, line).map(TerrainKind)).transpose()?,
+    };
+    if let Some(extra) = parts.next() {
+        return Err((Some(line), format!("unexpected `{}` after the terrain", extra)));
+    }
+    Ok(tile)
+}
+
+fn parse(text: &str) -> Result<Content, ParseError> {
+    let mut sections = split_sections(text)?;
+    let grounds = Ids::collect(&sections, "ground")?;
+    let terrain = Ids::collect(&sections, "terrain")?;
+    let items = Ids::collect(&sections, "item")?;
+    let creatures = Ids::collect(&sections, "creature")?;
+    let mut content = Content {
+        grounds: Vec::new(),
+        terrain: Vec::new(),
+        items: Vec::new(),
+        creatures: Vec::new(),
+        recipes: Vec::new(),
+        level_colors: HashMap::new(),
+        palette: Palette {
+            grass: GroundKind(grounds.require("grass")?),
+            rock: GroundKind(grounds.require("rock")?),
+            water: GroundKind(grounds.require("water")?),
+            tree: TerrainKind(terrain.require("tree")?),
+            cliff: TerrainKind(terrain.require("cliff")?),
+            entrance: TerrainKind(terrain.require("entrance")?),
+        },
+        pvp_zones: Vec::new(),
+    };
+    for section in sections.iter_mut() {
+        match section.category {
+            "ground" => content.grounds.push(GroundDef {
+                name: section.name(),
+                glyph: section.required("glyph").and_then(|(v, l)| glyph(v, l))?,
+                fg: section.required("fg").and_then(|(v, l)| color(v, l))?,
+                bg: section.take("bg").map(|(v, l)| color(v, l)).transpose()?,
+                passable: section.take("passable").map_or(Ok(true), |(v, l)| flag(v, l))?,
+                spawns: section.take("spawns").map_or(Ok(Vec::new()), |(v, l)| counted_list(v, l, &creatures))?
+                    .into_iter().map(|(id, weight)| (CreatureKind(id), weight)).collect(),
+            }),
+            "terrain" => content.terrain.push(TerrainDef {
+                name: section.name(),
+                glyph: section.required("glyph").and_then(|(v, l)| glyph(v, l))?,
+                fg: section.required("fg").and_then(|(v, l)| color(v, l))?,
+                bg: section.take("bg").map(|(v, l)| color(v, l)).transpose()?,
+                passable: section.take("passable").map_or(Ok(false), |(v, l)| flag(v, l))?,
+                opaque: section.take("opaque").map_or(Ok(false), |(v, l)| flag(v, l))?,
+                hit_points: section.take("hp").map(|(v, l)| number(v, l)).transpose()?,
+                drops: section.take("drops").map(|(v, l)| items.get(v, l).map(ItemKind)).transpose()?,
+                leaves: section.take("leaves").map(|(v, l)| grounds.get(v, l).map(GroundKind)).transpose()?,
+                needs_tool: section.take("needs_tool").map_or(Ok(false), |(v, l)| flag(v, l))?,
+                light: section.take("light").map(|(v, l)| positive(v, l)).transpose()?,
+                entrance: section.take("entrance").map_or(Ok(false), |(v, l)| flag(v, l))?,
+            }),
+            "item" => {
+                let (stack, stack_line) = section.take("stack").map_or(Ok((1, section.line)), |(v, l)| Ok((positive(v, l)?, l)))?;
+                let durability = section.take("durability").map(|(v, l)| positive(v, l)).transpose()?;
+                // Stacked items share one durability, so worn tools would be repaired by stacking.
+                if durability.is_some() && stack > 1 {
+                    return Err((Some(stack_line), format!("{} has durability, so it cannot stack", section.describe())));
+                }
+                content.items.push(ItemDef {
+                    name: section.name(),
+                    stack,
+                    weight: section.take("weight").map_or(Ok(1), |(v, l)| number(v, l))?,
+                    slot: section.take("slot").map(|(v, l)| match v {
+                        "hand" => Ok(EquipSlot::Hand),
+                        "body" => Ok(EquipSlot::Body),
+                        _ => Err((Some(l), format!("expected `hand` or `body`, found `{}`", v))),
+                    }).transpose()?,
+                    durability,
+                    damage: section.take("damage").map_or(Ok(1), |(v, l)| number(v, l))?,
+                    defense: section.take("defense").map_or(Ok(0), |(v, l)| number(v, l))?,
+                    power: section.take("power").map_or(Ok(Vec::new()), |(v, l)| counted_list(v, l, &terrain))?
+                        .into_iter().map(|(id, power)| (TerrainKind(id), power)).collect(),
+                    food: section.take("food").map(|(v, l)| number(v, l)).transpose()?,
+                    light: section.take("light").map(|(v, l)| positive(v, l)).transpose()?,
+                    places: section.take("places").map(|(v, l)| match v.split_once(' ') {
+                        Some(("terrain", id)) => terrain.get(id.trim(), l).map(|id| Placement::Terrain(TerrainKind(id))),
+                        Some(("ground", id)) => grounds.get(id.trim(), l).map(|id| Placement::Ground(GroundKind(id))),
+                        _ => Err((Some(l), format!("expected `terrain <id>` or `ground <id>`, found `{}`", v))),
+                    }).transpose()?,
+                });
+            }
+            "creature" => content.creatures.push(CreatureDef {
+                name: section.name(),
+                glyph: section.required("glyph").and_then(|(v, l)| glyph(v, l))?,
+                fg: section.required("fg").and_then(|(v, l)| color(v, l))?,
+                hp: section.required("hp").and_then(|(v, l)| positive(v, l))?,
+                damage: section.required("damage").and_then(|(v, l)| number(v, l))?,
+                sight: section.required("sight").and_then(|(v, l)| number(v, l))?,
+                think_delay: section.required("think").and_then(|(v, l)| positive(v, l))?,
+                loot: section.take("loot").map_or(Ok(Vec::new()), |(v, l)| counted_list(v, l, &items))?
+                    .into_iter().map(|(id, n)| (ItemKind(id), n)).collect(),
+                reaction: section.required("reaction").and_then(|(v, l)| match v {
+                    "chase" => Ok(Reaction::Chase),
+                    "flee" => Ok(Reaction::Flee),
+                    _ => Err((Some(l), format!("expected `chase` or `flee`, found `{}`", v))),
+                })?,
+            }),
+            "recipe" => content.recipes.push(Recipe {
+                name: section.name(),
+                inputs: section.required("inputs").and_then(|(v, l)| counted_list(v, l, &items))?
+                    .into_iter().map(|(id, n)| (ItemKind(id), n)).collect(),
+                outputs: section.required("outputs").and_then(|(v, l)| counted_list(v, l, &items))?
+                    .into_iter().map(|(id, n)| (ItemKind(id), n)).collect(),
+                station: section.take("station").map(|(v, l)| terrain.get(v, l).map(TerrainKind)).transpose()?,
+            }),
+            "level" => {
+                let rgb = section.id.split_whitespace()
+                    .map(|part| part.parse::<u8>().ok())
+                    .collect::<Option<Vec<_>>>();
+                let rgb = match rgb.as_deref() {
+                    Some(&[r, g, b]) => (r, g, b),
+                    _ => return Err((Some(section.line), format!("a level colour is three numbers from 0 to 255, found `{}`", section.id))),
+                };
+                let surface = section.required("surface").and_then(|(v, l)| tile(v, l, &grounds, &terrain))?;
+                let underground = section.required("underground").and_then(|(v, l)| tile(v, l, &grounds, &terrain))?;
+                if content.level_colors.insert(rgb, (surface, underground)).is_some() {
+                    return Err((Some(section.line), format!("level colour `{}` is defined twice", section.id)));
+                }
+            }
+            "pvp_zone" => {
+                let layer = section.take("layer").map_or(Ok(0), |(v, l)| number(v, l))?;
+                let (x0, y0) = section.required("from").and_then(|(v, l)| point(v, l))?;
+                let (x1, y1) = section.required("to").and_then(|(v, l)| point(v, l))?;
+                content.pvp_zones.push(PvpZone {
+                    min: geom::Vec::new_in_layer(x0.min(x1), y0.min(y1), layer),
+                    max: geom::Vec::new_in_layer(x0.max(x1), y0.max(y1), layer),
+                    pvp: section.required("pvp").and_then(|(v, l)| flag(v, l))?,
+                });
+            }
+            other => return Err((Some(section.line), format!("unknown category `{}`", other))),
+        }
+        section.finish()?;
+    }
+    if let Some(extra) = sections.iter().filter(|section| section.category == "recipe").nth(MAX_RECIPES) {
+        return Err((Some(extra.line), format!("there can be at most {} recipes", MAX_RECIPES)));
+    }
+    Ok(content)
+}
@@ -1,8 +1,9 @@
 use std::ops::*;
 use serde::{Serialize, Deserialize};
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
-pub struct Vec { pub x: i32, pub y:i32 }
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
+/// A position or offset. `z` is the world layer: 0 at the surface, lower underground.
+pub struct Vec { pub x: i32, pub y:i32, pub z: i32 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Dir(Vec);
@@ -10,31 +11,45 @@ pub struct Dir(Vec);
 impl Add for Vec {
     type Output = Vec;
     fn add(self, other: Self) -> Self {
-        Vec { x : self.x + other.x, y : self.y + other.y }
+        Vec { x : self.x + other.x, y : self.y + other.y, z : self.z + other.z }
     }
 }
 impl AddAssign for Vec {
     fn add_assign(&mut self, other: Self) {
         self.x += other.x;
         self.y += other.y;
+        self.z += other.z;
     }
 }
 impl Sub for Vec {
     type Output = Vec;
     fn sub(self, other: Self) -> Self {
-        Vec { x : self.x - other.x, y : self.y - other.y }
+        Vec { x : self.x - other.x, y : self.y - other.y, z : self.z - other.z }
     }
 }
 impl SubAssign for Vec {
     fn sub_assign(&mut self, other: Self) {
         self.x -= other.x;
         self.y -= other.y;
+        self.z -= other.z;
     }
 }
 
 impl Vec {
+    /// A position on the surface, or an offset within a layer.
     pub fn new(x: i32, y: i32) -> Vec {
-        Vec { x , y }
+        Vec { x , y, z: 0 }
+    }
+    pub fn new_in_layer(x: i32, y: i32, z: i32) -> Vec {
+        Vec { x, y, z }
+    }
+    /// Squared length within a layer; the layer is ignored.
+    pub fn len_sq(self) -> i32 {
+        self.x * self.x + self.y * self.y
+    }
+    /// Manhattan length within a layer; the layer is ignored.
+    pub fn manhattan(self) -> i32 {
+        self.x.abs() + self.y.abs()
     }
 }
 impl Dir {
@@ -45,4 +60,5 @@ impl Dir {
     pub fn right() -> Dir { Dir(Vec::new(1, 0)) }
     pub fn up() -> Dir { Dir(Vec::new(0, -1)) }
     pub fn down() -> Dir { Dir(Vec::new(0, 1)) }
+    pub fn all() -> [Dir; 4] { [Dir::up(), Dir::left(), Dir::down(), Dir::right()] }
 }
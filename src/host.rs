@@ -2,7 +2,7 @@ use tokio::net::{TcpStream, TcpListener};
 use tokio::sync::mpsc::{self, Sender, Receiver};
 use tokio::sync::oneshot;
 use futures::stream::StreamExt;
-use tokio::time::{Instant, delay_until};
+use tokio::time::{Instant, interval};
 
 use std::time::Duration;
 use std::io;
@@ -13,11 +13,15 @@ use std::collections::HashMap;
 use crate::{ClientId, ToClientEvent, EventId, gen_event_id};
 use crate::killable::{spawn, KillHandle};
 use crate::terminal::Terminal;
-use crate::world::World;
+use crate::content::{self, CONTENT_PATH};
+use crate::world::{World, WorldEvent, WorldError, Entity, EntityKind, TICK_MILLIS};
+use crate::world::exploration::ExploredMap;
 use get_if_addrs::get_if_addrs;
 
 pub mod client;
+pub mod view;
 use self::client::Client;
+use self::view::View;
 
 type BoxErr = Box<dyn Error + Send + Sync + 'static>;
 
@@ -26,21 +30,26 @@ pub enum ClientEvent {
     ClientConnected(Client, oneshot::Sender<World>),
     ClientDisconnect(ClientId, Option<BoxErr>),
     WorldEvent(EventId, Option<ClientId>, crate::world::WorldEvent),
+    /// The client's player leaves the world, but the client stays connected.
+    Leave(ClientId),
+    /// A client that left puts its player back into the world.
+    Rejoin(ClientId),
+    /// A client's world diverged from ours and has to be replaced.
+    Resync(ClientId),
     Shutdown(),
 }
 
 pub async fn host_game(term: Terminal, local_name: String) {
-    match host_game_real(term.clone(), local_name).await {
-        Err(err) => {
-            eprintln!("Error in host: {}", err);
-        },
-        Ok(()) => {},
+    if let Err(err) = host_game_real(term.clone(), local_name).await {
+        eprintln!("Error in host: {}", err);
     }
 }
 async fn host_game_real(
     term: Terminal,
     local_name: String,
 ) -> io::Result<()> {
+    // Clients get the definitions from the host, so only the host reads the file.
+    content::load(CONTENT_PATH).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
     let accept = Acceptor::new().await?;
     fn to_ip(addr: get_if_addrs::IfAddr) -> String {
         use get_if_addrs::IfAddr::*;
@@ -60,7 +69,9 @@ async fn host_game_real(
     string.pop(); string.pop();
     let _ = term.println(format!("Listening on {}", string));
 
-    let mut host = Host::new();
+    let pvp = term.readln("Allow players to fight each other? (y/n)")
+        .is_ok_and(|answer| answer.trim() == "y");
+    let mut host = Host::new(World::default().with_pvp(pvp));
     let mut next_client_id = 1;
 
     let (sink, mut client_events) = mpsc::unbounded_channel();
@@ -76,7 +87,7 @@ async fn host_game_real(
         let world = local_world_recv.await.unwrap();
         std::thread::Builder::new().name("game loop".to_string())
             .spawn(move || {
-                crate::create_game_loop(worldio, world, local_id);
+                crate::create_game_loop(worldio, world, local_id, true);
             }).unwrap();
     });
     sink.send(ClientEvent::ClientConnected(local_client, local_world_send)).unwrap();
@@ -107,9 +118,20 @@ async fn host_game_real(
         }
     }));
 
+    // Drive the world clock; timers inside the world fire off these ticks.
+    let tick_sink = sink.clone();
+    tokio::spawn(async move {
+        let mut ticks = interval(Duration::from_millis(TICK_MILLIS));
+        loop {
+            ticks.tick().await;
+            if tick_sink.send(ClientEvent::WorldEvent(gen_event_id(), None, WorldEvent::Tick)).is_err() {
+                return;
+            }
+        }
+    });
+
     let server_start_time = Instant::now();
     while let Some(event) = client_events.recv().await {
-        eprintln!("Event: {:?}", event);
         match event {
             ClientEvent::ClientConnected(client, world_send) => {
                 // Broadcast new client id
@@ -118,12 +140,18 @@ async fn host_game_real(
 
                 // Add to list of clients.
                 let id = client.client_id;
+                let explored = host.explored.remove(&client.name);
                 host.add_client(client);
 
-                // Send world to new client.
-                let world = host.third_world.clone();
+                // Send the new client what it can know of the world, which is nothing in view yet.
+                let world = host.views[&id].world().clone();
                 let _ = world_send.send(world);
 
+                // Give back what the player explored last time, before they look around again.
+                if let Some(explored) = explored {
+                    sink.send(ClientEvent::WorldEvent(gen_event_id(), None, WorldEvent::RestoreExplored(id, explored))).unwrap();
+                }
+
                 // Create world event for entity.
                 let ev = host.third_world.create_player_spawn_event(id);
                 let ev = ClientEvent::WorldEvent(gen_event_id(), None, ev);
@@ -132,13 +160,14 @@ async fn host_game_real(
                 sink.send(ev).unwrap();
             },
             ClientEvent::ClientDisconnect(id, Some(err)) => {
-                let removed = host.clients.remove(&id).unwrap();
+                let removed = host.remove_client(id).unwrap();
 
                 let ev = host.third_world.create_player_exit_event(removed.client_id);
                 if let Some(ev) = ev {
                     let ev = ClientEvent::WorldEvent(gen_event_id(), None, ev);
                     sink.send(ev).unwrap();
                 }
+                host.forget_explored(&sink, id, Some(&removed.name));
 
                 host.broadcast(Instant::now() - server_start_time,
                     ToClientEvent::RemoveClientId(id));
@@ -150,56 +179,91 @@ async fn host_game_real(
                 ));
             },
             ClientEvent::ClientDisconnect(id, None) => {
-                let removed = host.clients.remove(&id).unwrap();
+                // The client may already be gone if a broadcast to it failed.
+                let removed = host.remove_client(id);
+
+                let ev = host.third_world.create_player_exit_event(id);
+                if let Some(ev) = ev {
+                    let ev = ClientEvent::WorldEvent(gen_event_id(), None, ev);
+                    sink.send(ev).unwrap();
+                }
+                host.forget_explored(&sink, id, removed.as_ref().map(|removed| removed.name.as_str()));
 
                 host.broadcast(Instant::now() - server_start_time, ToClientEvent::RemoveClientId(id));
 
-                let _ = term.println(format!(
-                    "Disconnected {}.",
-                    removed.name,
-                ));
+                if let Some(removed) = removed {
+                    let _ = term.println(format!(
+                        "Disconnected {}.",
+                        removed.name,
+                    ));
+                }
             },
-            ClientEvent::WorldEvent(evid, id, event) =>
+            ClientEvent::Leave(id) => {
+                if let Some(ev) = host.third_world.create_player_exit_event(id) {
+                    sink.send(ClientEvent::WorldEvent(gen_event_id(), None, ev)).unwrap();
+                }
+            },
+            ClientEvent::Rejoin(id) => {
+                if !host.third_world.has_player(id) {
+                    let ev = host.third_world.create_player_spawn_event(id);
+                    sink.send(ClientEvent::WorldEvent(gen_event_id(), None, ev)).unwrap();
+                }
+            },
+            ClientEvent::WorldEvent(evid, id, event) => {
+                // New entities get their ids here, so every client sees the same ones.
+                let event = match id {
+                    None => host.third_world.assign_id(event, false),
+                    Some(_) => event,
+                };
                 match host.third_world.handle_event(id, event.clone()) {
-                    Ok((next_world, mut events)) => {
-                        let now = Instant::now();
-                        host.broadcast(now - server_start_time, ToClientEvent::WorldEvent(evid, id, event));
+                    Ok((next_world, events)) => {
+                        let checksum = matches!(event, WorldEvent::Tick) && next_world.is_checksum_tick();
+                        let player = match &event {
+                            WorldEvent::SpawnEntity(entity, Entity { kind: EntityKind::Player(client), .. }) => Some((*client, *entity)),
+                            _ => None,
+                        };
                         host.third_world = next_world;
+                        host.broadcast(Instant::now() - server_start_time, ToClientEvent::WorldEvent(evid, id, event));
+                        if let Some((client, entity)) = player {
+                            if let Some(client) = host.clients.get_mut(&client) {
+                                let _ = client.send_event(Instant::now() - server_start_time, ToClientEvent::YourEntity(entity));
+                            }
+                        }
+                        // Sent right behind the event, so clients compare it against the same state.
+                        if checksum {
+                            host.broadcast_checksums(Instant::now() - server_start_time, evid);
+                        }
 
-                        // Sort events by decreasing time
-                        events.sort_by_key(|(time, _)| *time);
-                        events.reverse();
-                        // Send events with time zero
-                        while let Some((0, _)) = events.last() {
-                            let ev = events.pop().unwrap().1;
+                        for ev in events {
                             let _ = sink.send(ClientEvent::WorldEvent(gen_event_id(), None, ev));
                         }
-                        // Send the remaining using timers
-                        if events.len() > 0 {
-                            let sink = sink.clone();
-                            tokio::spawn(async move {
-                                for (time, ev) in events.into_iter().rev() {
-                                    delay_until(now + Duration::from_millis(time)).await;
-                                    match sink.send(ClientEvent::WorldEvent(gen_event_id(), None, ev)) {
-                                        Ok(()) => {},
-                                        Err(_) => return,
-                                    }
-                                }
-                            });
-                        }
                     },
-                    Err(error) => {
-                        if let Some(id) = id {
-                            let mut client = host.clients.remove(&id).unwrap();
-                            let _ = client.send_event(Instant::now() - server_start_time, ToClientEvent::Kick(
-                                    format!("Third world error: {:?}", error)));
-                        } else {
-                            term.println(
-                                format!("Third world error: {:?}", error)).unwrap();
-                            return Ok(());
-                        }
+                    // Rejected events leave the world untouched and are not broadcast.
+                    Err(error) => match (id, error) {
+                        // A client trying to control something that is not theirs is misbehaving.
+                        (Some(id), WorldError::Unauthorized) => {
+                            if let Some(mut client) = host.remove_client(id) {
+                                let _ = client.send_event(Instant::now() - server_start_time, ToClientEvent::Kick(
+                                        format!("Third world error: {}", WorldError::Unauthorized)));
+                            }
+                        },
+                        // The player died or the inventory changed before the action arrived.
+                        (Some(_), _) => {},
+                        // Follow-up and timer events for entities removed in the meantime.
+                        (None, WorldError::MissingEntity(_)) => {},
+                        (None, error) => {
+                            let _ = term.println(format!("Dropped world event {:?}: {}", event, error));
+                        },
                     },
-                },
+                }
+            },
+            ClientEvent::Resync(id) => {
+                if let (Some(client), Some(view)) = (host.clients.get_mut(&id), host.views.get(&id)) {
+                    let world = Box::new(view.world().clone());
+                    let _ = term.println(format!("Resynchronising {}.", client.name));
+                    let _ = client.send_event(Instant::now() - server_start_time, ToClientEvent::Resync(world));
+                }
+            },
             ClientEvent::Shutdown() => {
                 host.broadcast(Instant::now() - server_start_time, ToClientEvent::Kick("Server shutting down.".into()));
                 return Ok(());
@@ -212,33 +276,80 @@ async fn host_game_real(
 
 struct Host {
     clients: HashMap<ClientId, client::Client>,
+    /// What each client knows of `third_world`.
+    views: HashMap<ClientId, View>,
     third_world: World,
+    /// Explored maps of players who disconnected, by name, handed back when they reconnect.
+    explored: HashMap<String, ExploredMap>,
 }
 impl Host {
+    /// Take a disconnecting client's explored map out of the world, keeping it under the
+    /// player's name, so the world does not carry the maps of everyone who ever joined.
+    fn forget_explored(&mut self, sink: &mpsc::UnboundedSender<ClientEvent>, id: ClientId, name: Option<&str>) {
+        if let Some(explored) = self.third_world.explored(id) {
+            if let Some(name) = name {
+                self.explored.insert(name.to_string(), explored.clone());
+            }
+            let _ = sink.send(ClientEvent::WorldEvent(gen_event_id(), None, WorldEvent::ForgetExplored(id)));
+        }
+    }
+    /// Send a message to every client. A world event, which has to be applied to `third_world`
+    /// already, only goes to the clients whose view it concerns, and each client is then sent
+    /// whatever came into its view, left it or turned out differently there.
     pub fn broadcast(&mut self, since_start: Duration, msg: ToClientEvent) {
         let mut remove = Vec::new();
         for client in self.clients.values_mut() {
-            if !client.send_event(since_start, msg.clone()) {
+            let messages = match (&msg, self.views.get_mut(&client.client_id)) {
+                (ToClientEvent::WorldEvent(_, owner, ev), Some(view)) => {
+                    let (forward, changes) = view.update(&self.third_world, *owner, ev);
+                    forward.then(|| msg.clone()).into_iter()
+                        .chain(changes.into_iter().map(|change| ToClientEvent::WorldEvent(gen_event_id(), None, change)))
+                        .collect()
+                }
+                _ => vec![msg.clone()],
+            };
+            if !messages.into_iter().all(|msg| client.send_event(since_start, msg)) {
                 remove.push(client.client_id);
             }
         }
         for id in remove {
-            self.clients.remove(&id);
+            self.remove_client(id);
+        }
+    }
+    /// Send every client the checksum of its view, right after event `evid`.
+    pub fn broadcast_checksums(&mut self, since_start: Duration, evid: EventId) {
+        let time = self.third_world.time();
+        let mut remove = Vec::new();
+        for client in self.clients.values_mut() {
+            if let Some(view) = self.views.get(&client.client_id) {
+                if !client.send_event(since_start, ToClientEvent::Checksum(evid, time, view.world().checksum())) {
+                    remove.push(client.client_id);
+                }
+            }
+        }
+        for id in remove {
+            self.remove_client(id);
         }
     }
 }
 
 impl Host {
-    pub fn new() -> Self {
-        #[allow(unreachable_code)]
+    pub fn new(world: World) -> Self {
         Host {
             clients: HashMap::new(),
-            third_world: Default::default(),
+            views: HashMap::new(),
+            third_world: world,
+            explored: HashMap::new(),
         }
     }
     pub fn add_client(&mut self, client: client::Client) {
+        self.views.insert(client.client_id, View::new(client.client_id, &self.third_world));
         self.clients.insert(client.client_id, client);
     }
+    pub fn remove_client(&mut self, id: ClientId) -> Option<client::Client> {
+        self.views.remove(&id);
+        self.clients.remove(&id)
+    }
 }
 
 struct Acceptor {
@@ -13,6 +13,7 @@ use crate::terminal::Terminal;
 use crate::connection::{split_stream, ConnectionIn, ConnectionOut};
 use crate::host::ClientEvent;
 use crate::world::World;
+use crate::content::{content, Content};
 use crate::killable::{KillSpawn, KillHandle};
 
 type BoxErr = Box<dyn Error + Send + Sync + 'static>;
@@ -23,10 +24,11 @@ pub enum ClientChannel<V> {
     Crossbeam(crossbeam::channel::Sender<V>),
 }
 impl<V> ClientChannel<V> {
-    pub fn try_send(&mut self, v: V) -> Result<(), ()> {
+    /// Returns false if the message could not be queued.
+    pub fn try_send(&mut self, v: V) -> bool {
         match self {
-            ClientChannel::Tokio(chan) => chan.try_send(v).map_err(|_| ()),
-            ClientChannel::Crossbeam(chan) => chan.try_send(v).map_err(|_| ()),
+            ClientChannel::Tokio(chan) => chan.try_send(v).is_ok(),
+            ClientChannel::Crossbeam(chan) => chan.try_send(v).is_ok(),
         }
     }
 }
@@ -50,13 +52,22 @@ pub fn local_client(
     tokio::spawn(async move {
         while let Some(msg) = recv.recv().await {
             let client_msg = match msg {
+                // The server outlives the local player; only an explicit shutdown stops it.
                 FromClientEvent::Disconnect() =>
+                    ClientEvent::ClientDisconnect(id, None),
+                FromClientEvent::Leave() =>
+                    ClientEvent::Leave(id),
+                FromClientEvent::Rejoin() =>
+                    ClientEvent::Rejoin(id),
+                FromClientEvent::Shutdown() =>
                     ClientEvent::Shutdown(),
+                FromClientEvent::Resync() =>
+                    ClientEvent::Resync(id),
 
                 FromClientEvent::PlayerEvent(evid, world) =>
                     ClientEvent::WorldEvent(evid, Some(id), world),
             };
-            if let Err(_) = sink.send(client_msg) {
+            if sink.send(client_msg).is_err() {
                 break;
             }
         }
@@ -77,7 +88,7 @@ pub struct Client {
 impl Client {
     #[must_use]
     pub fn send_event(&mut self, since_start: Duration, ev: crate::ToClientEvent) -> bool {
-        self.send_events.try_send((since_start, ev)).is_ok()
+        self.send_events.try_send((since_start, ev))
     }
 }
 impl Drop for Client {
@@ -160,6 +171,7 @@ async fn start_client_task(mut inner: ClientInner, handle: KillHandle) {
             Ok(world) => world,
             Err(_) => return Ok(()), // game has shut down
         };
+        inner.output.send::<Content>(content()).await?;
         inner.output.send::<World>(&world).await?;
 
         let (spawn1, handle1) = KillSpawn::new();
@@ -213,11 +225,19 @@ impl ClientReceiver {
             let msg: FromClientEvent = self.input.recv().await?;
             let client_msg = match msg {
                 FromClientEvent::Disconnect() => return Ok(()),
+                FromClientEvent::Leave() =>
+                    ClientEvent::Leave(self.client_id),
+                FromClientEvent::Rejoin() =>
+                    ClientEvent::Rejoin(self.client_id),
+                // Only the player on the host may shut the server down.
+                FromClientEvent::Shutdown() => continue,
+                FromClientEvent::Resync() =>
+                    ClientEvent::Resync(self.client_id),
 
                 FromClientEvent::PlayerEvent(evid, world) =>
                     ClientEvent::WorldEvent(evid, Some(self.client_id), world),
             };
-            if let Err(_) = self.sink.send(client_msg) {
+            if self.sink.send(client_msg).is_err() {
                 break Ok(());
             }
         }
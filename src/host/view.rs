@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+
+use crate::ClientId;
+use crate::geom::Vec;
+use crate::world::{World, WorldEvent, Entity, EntityId, EntityKind, PlayerActionEvent};
+use crate::world::fov::{Fov, VIEW_RADIUS};
+
+/// The part of the world one client knows about. The host keeps it exactly as the client has
+/// it: both start from the same copy and apply the same events. After every event it is
+/// compared with the host's world within the player's field of view, and whatever differs is
+/// sent on, so entities appear as they come into view and disappear as they leave it.
+pub struct View {
+    client: ClientId,
+    world: World,
+    /// Where the client's player stood when the field of view was worked out, and what it is.
+    fov: Option<(Vec, Fov)>,
+}
+
+impl View {
+    pub fn new(client: ClientId, world: &World) -> View {
+        View { client, world: world.public_view(), fov: None }
+    }
+    /// The client's copy of the world.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    /// Take in `ev`, which `world` is the result of on the host. Returns whether the event
+    /// itself should be sent to the client, and the changes to send after it, in order.
+    pub fn update(&mut self, world: &World, owner: Option<ClientId>, ev: &WorldEvent) -> (bool, std::vec::Vec<WorldEvent>) {
+        let forward = self.concerns(ev) && match self.world.handle_event(owner, ev.clone()) {
+            Ok((next, _)) => {
+                self.world = next;
+                true
+            }
+            // The client would reject it too; whatever it changed is sent as it is instead.
+            Err(_) => false,
+        };
+        let changes = self.reconcile(world, ev);
+        for change in &changes {
+            self.world = self.world.handle_event(None, change.clone())
+                .expect("changes to a view always apply").0;
+        }
+        (forward, changes)
+    }
+    /// Whether the client may know about the event, which it then works out for itself.
+    fn concerns(&self, ev: &WorldEvent) -> bool {
+        use WorldEvent::*;
+        let known = |id: &EntityId| self.world.get::<EntityKind>(*id).is_some();
+        match ev {
+            Tick => true,
+            // Creatures decide with the world's random numbers and players the client may not
+            // know about, so the client only learns where they end up.
+            CreatureThink(_) | SpawnCreatures => false,
+            // New entities are shown once they are in view.
+            SpawnEntity(..) | CreateEntity(_) => false,
+            Notify(id, _) => self.world.get::<EntityKind>(*id).is_some_and(|kind| kind.is_player(self.client)),
+            Respawn(client) | CancelRespawn(client) | ForgetExplored(client) | RestoreExplored(client, _) =>
+                *client == self.client,
+            PlayerAction(id, _) | DeleteEntity(id) | Enter(id, _) | SurvivalTick(id) => known(id),
+            ShowEntity(..) | HideEntity(_) | TileChanged(..) | RespawnTime(..) => false,
+        }
+    }
+    /// Bring the client's copy in line with `world` within the player's field of view.
+    fn reconcile(&mut self, world: &World, ev: &WorldEvent) -> std::vec::Vec<WorldEvent> {
+        let player = world.player_entity(self.client);
+        let origin = player.and_then(|id| world.get::<Vec>(id)).copied();
+        let mut changes = std::vec::Vec::new();
+        // Building and breaking tiles can open up or block the view.
+        let near_origin = |pos: Vec| origin.is_some_and(|origin| origin.z == pos.z && (pos - origin).len_sq() <= VIEW_RADIUS * VIEW_RADIUS);
+        let tile_changed = match ev {
+            WorldEvent::PlayerAction(id, PlayerActionEvent::Attack(dir)) |
+            WorldEvent::PlayerAction(id, PlayerActionEvent::Build(_, dir)) =>
+                world.get::<Vec>(*id).is_some_and(|pos| near_origin(*pos + dir.to_vec())),
+            _ => false,
+        };
+        if tile_changed || self.fov.as_ref().map(|(at, _)| *at) != origin {
+            self.fov = origin.map(|origin| (origin, world.field_of_view(origin, VIEW_RADIUS)));
+            for pos in self.fov.iter().flat_map(|(_, fov)| fov.iter()) {
+                let (tile, damage) = (world.tiles.get(pos), world.tile_damage(pos));
+                if self.world.tiles.get(pos) != tile || self.world.tile_damage(pos) != damage {
+                    changes.push(WorldEvent::TileChanged(pos, tile, damage));
+                }
+            }
+        }
+        // The client shows its own death screen, even when something it could not see did it in.
+        let respawn = world.respawn_time(self.client);
+        if self.world.respawn_time(self.client) != respawn {
+            changes.push(WorldEvent::RespawnTime(self.client, respawn));
+        }
+        let visible = match &self.fov {
+            Some((origin, fov)) => {
+                let span = Vec::new(VIEW_RADIUS, VIEW_RADIUS);
+                world.query_in::<&Vec>(*origin - span, *origin + span)
+                    .filter(|(_, pos)| fov.contains(**pos))
+                    .map(|(id, _)| id)
+                    .chain(player)
+                    .collect()
+            }
+            None => BTreeSet::new(),
+        };
+        for (id, _) in self.world.query::<&EntityKind>() {
+            if !visible.contains(&id) {
+                changes.push(WorldEvent::HideEntity(id));
+            }
+        }
+        for id in visible {
+            let entity = world.entity(id).expect("visible entities exist");
+            if !same_entity(self.world.entity(id).as_ref(), &entity) {
+                changes.push(WorldEvent::ShowEntity(id, entity));
+            }
+        }
+        changes
+    }
+}
+
+/// Components are compared by their serialized form, which is what the client gets anyway.
+fn same_entity(known: Option<&Entity>, entity: &Entity) -> bool {
+    known.is_some_and(|known| bincode::serialize(known).ok() == bincode::serialize(entity).ok())
+}
@@ -7,6 +7,7 @@ use std::time::Duration;
 
 use crate::{ClientId, FromClientEvent, ToClientEvent};
 use crate::world::World;
+use crate::content::{self, Content};
 use crate::terminal::Terminal;
 use crate::connection::split_stream;
 
@@ -19,11 +20,8 @@ pub enum ServerEvent {
 }
 
 pub async fn join_game(term: Terminal, ip: String, name: String) {
-    match join_game_real(term.clone(), ip, name).await {
-        Err(err) => {
-            let _ = term.println(format!("Error in join: {}", err));
-        },
-        Ok(()) => {},
+    if let Err(err) = join_game_real(term.clone(), ip, name).await {
+        let _ = term.println(format!("Error in join: {}", err));
     }
 }
 async fn join_game_real(
@@ -47,13 +45,15 @@ async fn join_game_real(
     let id = ClientId(id);
     term.println("Successfully connected. Receiving world.").unwrap();
 
+    let definitions: Content = input.recv().await?;
+    content::install(definitions);
     let world: World = input.recv().await?;
 
     let (netio, worldio) = crate::net_world_channel(term);
 
     std::thread::Builder::new().name("game loop".to_string())
         .spawn(move || {
-            crate::create_game_loop(worldio, world, id);
+            crate::create_game_loop(worldio, world, id, false);
         }).unwrap();
 
     let send = netio.send;
@@ -71,7 +71,7 @@ async fn join_game_real(
 
     loop {
         let msg = input.recv::<(Duration, ToClientEvent)>().await?;
-        if let Err(_) = send.send(msg) {
+        if send.send(msg).is_err() {
             break;
         }
     }
@@ -1,14 +1,19 @@
 use png_pong;
-use pix::Rgba8;
+use pix::{Raster, Rgba8};
 use std::collections::HashMap;
-use crate::world::{Tile, TileMap, GroundKind, TerrainKind, RoofKind, Chunk, CHUNK_SIZE};
+use crate::world::{Tile, TileMap, Chunk, CHUNK_SIZE};
+use crate::content::content;
+use crate::world::layers::{SURFACE, UNDERGROUND};
 use crate::geom::Vec;
 
-pub fn load_level() -> TileMap {
-    let data = std::fs::read("data/level.png").expect("Failed to open level file.");
+pub const LEVEL_PATH: &str = "data/level.png";
+
+/// The level image with its anchor pixels painted over, and the position of the origin anchor.
+fn read_level() -> Result<(Raster<Rgba8>, Vec), String> {
+    let data = std::fs::read(LEVEL_PATH).map_err(|err| err.to_string())?;
     let data = std::io::Cursor::new(data);
     let decoder = png_pong::FrameDecoder::<_, Rgba8>::new(data);
-    let mut raster = decoder.last().expect("No frames in png").expect("PNG parsing error").raster;
+    let mut raster = decoder.last().ok_or("No frames in png")?.map_err(|err| format!("PNG parsing error: {:?}", err))?.raster;
     let mut anchor_colors = HashMap::new();
     anchor_colors.insert((0, 0, 0, 255), "origin".to_string());
     let anchor_colors = anchor_colors;
@@ -24,24 +29,37 @@ pub fn load_level() -> TileMap {
             }
         }
     }
-    let origin = *anchors.get(&"origin".to_string()).unwrap();
+    let origin = *anchors.get(&"origin".to_string()).ok_or("The level has no origin anchor")?;
+    Ok((raster, origin))
+}
+
+/// Make sure every colour in the level image stands for a tile in `level_colors`.
+pub fn check_level(level_colors: &HashMap<(u8, u8, u8), (Tile, Tile)>) -> Result<(), String> {
+    let (raster, _) = read_level()?;
+    for pixel in raster.as_slice() {
+        let pixel = pixel.to_tuple();
+        let rgb = (pixel.0, pixel.1, pixel.2);
+        if pixel.3 != 0 && !level_colors.contains_key(&rgb) {
+            return Err(format!("the level image uses colour `{} {} {}`, which has no [level] section", rgb.0, rgb.1, rgb.2));
+        }
+    }
+    Ok(())
+}
+
+/// Build the tiles of the level image. Its colours are checked when the content is loaded.
+pub fn load_level() -> TileMap {
+    let (raster, origin) = read_level().expect("Failed to read level file.");
     let left = (origin.x + (CHUNK_SIZE - 1) as i32) / CHUNK_SIZE as i32;
     let top = (origin.y + (CHUNK_SIZE - 1) as i32) / CHUNK_SIZE as i32;
     let right = (raster.width() as i32 - origin.x) / CHUNK_SIZE as i32;
     let bottom = (raster.height() as i32 - origin.y) / CHUNK_SIZE as i32;
-    let mut tile_types = HashMap::new();
-    tile_types.insert((0, 255, 0, 255), Tile { ground : Some(GroundKind::Grass), terrain : None, roof: None});
-    tile_types.insert((0, 0, 255, 255), Tile { ground : Some(GroundKind::Water), terrain : None, roof: None});
-    tile_types.insert((0, 127, 0, 255), Tile { ground : Some(GroundKind::Grass), terrain : Some(TerrainKind::Tree), roof: None});
-    tile_types.insert((127, 51, 0, 255), Tile { ground : Some(GroundKind::Rock), terrain : Some(TerrainKind::Cliff), roof : Some(RoofKind::Mountain)});
-    tile_types.insert((70, 28, 0, 255), Tile { ground : Some(GroundKind::Rock), terrain : None, roof : Some(RoofKind::Mountain)});
-    tile_types.insert((195, 78, 0, 255), Tile { ground : Some(GroundKind::Rock), terrain : Some(TerrainKind::Entrance), roof : Some(RoofKind::Mountain)});
-    tile_types.insert((70, 0, 127, 255), Tile { ground : Some(GroundKind::Water), terrain : None, roof : Some(RoofKind::Mountain)});
-    let tile_types = tile_types;
+    // Each colour stands for a tile on the surface and the tile below it underground, as set in the content file.
+    let tile_types = &content().level_colors;
     let mut tile_map = TileMap::new();
     for cx in -left .. right {
         for cy in -top .. bottom {
-            let mut chunk : Chunk = Default::default();
+            let mut surface : Chunk = Default::default();
+            let mut underground : Chunk = Default::default();
             let mut non_empty = false;
             for px in 0 .. CHUNK_SIZE as i32 {
                 for py in 0 .. CHUNK_SIZE as i32 {
@@ -54,17 +72,19 @@ pub fn load_level() -> TileMap {
                     if pixel.3 == 0 {
                         continue;
                     }
-                    match tile_types.get(&pixel) {
-                        None => panic!("Missing tile type! {:?}", pixel),
-                        Some(tile) => {
-                            chunk[px as usize][py as usize] = tile.clone();
+                    match tile_types.get(&(pixel.0, pixel.1, pixel.2)) {
+                        None => continue,
+                        Some((above, below)) => {
+                            surface[px as usize][py as usize] = above.clone();
+                            underground[px as usize][py as usize] = below.clone();
                             non_empty = true;
                         }
                     }
                 }
             }
             if non_empty {
-                tile_map.set_chunk(cx, cy, chunk);
+                tile_map.set_chunk(SURFACE, cx, cy, surface);
+                tile_map.set_chunk(UNDERGROUND, cx, cy, underground);
             }
         }
     }
@@ -0,0 +1,94 @@
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use rand::random;
+
+#[derive(Debug)]
+pub enum LobbyCommand {
+    StartGame
+}
+
+#[derive(Debug, Copy, Clone, Hash, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ClientId(u64);
+
+impl From<u64> for ClientId {
+    fn from(id: u64) -> ClientId {
+        ClientId(id)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EventId(u64);
+
+pub fn gen_event_id() -> EventId {
+    EventId(random())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum FromClientEvent {
+    /// Client wants to disconnect.
+    Disconnect(),
+    /// Client stays connected but removes its player from the world.
+    Leave(),
+    /// Client that left wants its player back.
+    Rejoin(),
+    /// Shut the whole server down; only honoured from the hosting client.
+    Shutdown(),
+    /// Client's world no longer matches the host's and needs a fresh copy.
+    Resync(),
+    /// A player event.
+    PlayerEvent(EventId, crate::world::WorldEvent),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ToClientEvent {
+    NewClientId(ClientId),
+    RemoveClientId(ClientId),
+    Kick(String),
+    WorldEvent(EventId, Option<ClientId>, crate::world::WorldEvent),
+    /// Checksum of the host's world right after the given event, along with its world time.
+    Checksum(EventId, u64, u64),
+    /// The host's current world, replacing the client's.
+    Resync(Box<world::World>),
+    /// The entity the receiving client plays, sent each time its player spawns.
+    YourEntity(world::EntityId),
+}
+
+pub struct NetIOHalf {
+    pub term: terminal::Terminal,
+    pub send: crossbeam::channel::Sender<(Duration, ToClientEvent)>,
+    pub recv: tokio::sync::mpsc::UnboundedReceiver<FromClientEvent>,
+}
+
+pub struct WorldIOHalf {
+    pub term: terminal::Terminal,
+    pub send: tokio::sync::mpsc::UnboundedSender<FromClientEvent>,
+    pub recv: crossbeam::channel::Receiver<(Duration, ToClientEvent)>,
+}
+
+pub fn net_world_channel(term: terminal::Terminal) -> (NetIOHalf, WorldIOHalf) {
+    let (to_client_send, to_client_recv) = tokio::sync::mpsc::unbounded_channel();
+    let (from_client_send, from_client_recv) = crossbeam::channel::unbounded();
+    let term2 = term.clone();
+    (
+        NetIOHalf { term, send: from_client_send, recv: to_client_recv, },
+        WorldIOHalf { term: term2, send: to_client_send, recv: from_client_recv, },
+    )
+}
+
+/// This will be called in a newly created thread dedicated to the game loop.
+/// `hosting` is set for the client playing on the host, which may shut the server down.
+pub fn create_game_loop(io: WorldIOHalf, world: world::World, my_id: ClientId, hosting: bool) {
+    world_handler::handle_world(io, world, my_id, hosting)
+}
+
+pub mod terminal;
+pub mod connection;
+pub mod host;
+pub mod join;
+pub mod killable;
+pub mod world;
+pub mod geom;
+pub mod renderer;
+pub mod world_handler;
+pub mod level_loader;
+pub mod content;
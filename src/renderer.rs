@@ -1,4 +1,9 @@
-use crate::world::{World, EntityId, Tile, GroundKind, TerrainKind, RoofKind};
+use crate::world::{TICK_MILLIS, World, EntityId, EntityKind, Health, Inventory, Item, Tile};
+use crate::world::survival::{Survival, MAX_FOOD, MAX_STAMINA};
+use crate::world::equipment::Equipment;
+use crate::world::trading::Trade;
+use crate::world::lighting::MAX_LIGHT;
+use crate::world::fov::VIEW_RADIUS;
 use crate::terminal::Scene;
 use crate::terminal;
 use crate::geom::Vec;
@@ -6,9 +11,9 @@ use termion::color::AnsiValue;
 
 pub fn render(world: &World, player_id: &EntityId) -> Box<Scene> {
     let mut scene = Box::new(Scene::default());
-    let player = world.entities.get(&player_id).unwrap();
-    let player_roof = world.tiles.get(player.pos).roof;
-    let offset = player.pos - Vec::new(terminal::SCREEN_W as i32/2, terminal::SCREEN_H as i32/2);
+    let player_pos = *world.get::<Vec>(*player_id).unwrap();
+    let player_kind = *world.get::<EntityKind>(*player_id).unwrap();
+    let offset = player_pos - Vec::new(terminal::SCREEN_W as i32/2, terminal::SCREEN_H as i32/2);
     for sx in 0 .. terminal::SCREEN_W {
         for sy in 0 .. terminal::SCREEN_H {
             let world_pos = offset + Vec::new(sx as i32, sy as i32);
@@ -17,54 +22,150 @@ pub fn render(world: &World, player_id: &EntityId) -> Box<Scene> {
             let back_ch = [' ', '|', '-', '+'][gridline_x+gridline_y*2];
             scene.set_point(sx as i32, sy as i32, back_ch, AnsiValue::rgb(5, 5, 5), Some(AnsiValue::rgb(0, 0, 0)));
             let tile = world.tiles.get(world_pos);
-            render_tile(tile, &mut scene, sx as i32, sy as i32, &player_roof);
+            render_tile(tile, world.tile_damage(world_pos), &mut scene, sx as i32, sy as i32);
         }
     }
-    for entity in world.entities.values() {
-        let screen_pos = entity.pos - offset;
-        scene.set_point(screen_pos.x, screen_pos.y, '@', AnsiValue::rgb(5, 5, 5), None);
+    let screen_max = offset + Vec::new(terminal::SCREEN_W as i32 - 1, terminal::SCREEN_H as i32 - 1);
+    let fov = world.field_of_view(player_pos, VIEW_RADIUS);
+    for (_, (pos, kind)) in world.query_in::<(&Vec, &EntityKind)>(offset, screen_max).filter(|(_, (pos, _))| fov.contains(**pos)) {
+        let screen_pos = *pos - offset;
+        let (ch, color) = entity_glyph(kind);
+        scene.set_point(screen_pos.x, screen_pos.y, ch, color, None);
     }
-    let (hp, maxhp) = player.hp.unwrap();
-    scene.write(format!("HP: {}/{}", hp, maxhp), 0, 0);
-    scene.write(format!("Inventory: {}", player.inventory.as_ref().unwrap().count()), 0, 1);
-    scene
-}
-
-fn render_tile(tile: Tile, scene: &mut Scene, sx: i32, sy: i32, player_roof: &Option<RoofKind>) {
-    match tile.ground {
-        None => {}
-        Some(GroundKind::Grass) => {
-            scene.set_point(sx, sy, '"', AnsiValue::rgb(0, 2, 0), Some(AnsiValue::rgb(0, 3, 0)));
-        }
-        Some(GroundKind::Water) => {
-            scene.set_point(sx, sy, '~', AnsiValue::rgb(3, 3, 5), Some(AnsiValue::rgb(0, 0, 5)));
-        }
-        Some(GroundKind::Rock) => {
-            scene.set_point(sx, sy, '.', AnsiValue::rgb(3, 1, 0), Some(AnsiValue::rgb(0, 0, 0)));
+    // Darken everything by its light level, blacking out what is out of sight unless it has
+    // been seen before.
+    let lights = world.light_map(offset, screen_max);
+    let explored = match player_kind {
+        EntityKind::Player(client) => world.explored(client),
+        _ => None,
+    };
+    for sx in 0 .. terminal::SCREEN_W as i32 {
+        for sy in 0 .. terminal::SCREEN_H as i32 {
+            let world_pos = offset + Vec::new(sx, sy);
+            if !fov.contains(world_pos) {
+                if explored.is_some_and(|explored| explored.contains(world_pos)) {
+                    scene.mute(sx, sy);
+                } else {
+                    scene.shade(sx, sy, 0, MAX_LIGHT);
+                }
+                continue;
+            }
+            scene.shade(sx, sy, lights.get(world_pos), MAX_LIGHT);
         }
     }
-    let mut hide_roof = false;
-    match tile.terrain {
-        None => {}
-        Some(TerrainKind::Tree) => {
-            scene.set_point(sx, sy, 'Δ', AnsiValue::rgb(0, 0, 0), None);
-        }
-        Some(TerrainKind::Cliff) => {
-            scene.set_point(sx, sy, '#', AnsiValue::rgb(0, 0, 0), Some(AnsiValue::rgb(3, 1, 0)));
-        }
-        Some(TerrainKind::Entrance) => {
-            scene.set_point(sx, sy, 'O', AnsiValue::rgb(5, 5, 5), Some(AnsiValue::rgb(0, 0, 0)));
-            hide_roof = true;
-        }
+    // Players always know where they are, even in the dark.
+    let (ch, color) = entity_glyph(&player_kind);
+    let screen_pos = player_pos - offset;
+    scene.set_point(screen_pos.x, screen_pos.y, ch, color, None);
+    let health = world.get::<Health>(*player_id).unwrap();
+    let mut status = format!("HP: {}/{}  Time: {}", health.hp, health.max, world.day_phase().name());
+    if let Some(survival) = world.get::<Survival>(*player_id) {
+        status.push_str(&format!("  Food: {}/{}  Stamina: {}/{}", survival.food, MAX_FOOD, survival.stamina, MAX_STAMINA));
+    }
+    if world.pvp_at(player_pos) {
+        status.push_str("  PvP");
     }
-    if &tile.roof != player_roof && !hide_roof {
-        match tile.roof {
-            None => {
-                scene.set_point(sx, sy, '.', AnsiValue::rgb(5, 5, 5), Some(AnsiValue::rgb(0, 0, 0)));
+    scene.write(status, 0, 0);
+    let equipment = world.get::<Equipment>(*player_id);
+    let hand = equipment.and_then(|eq| eq.hand.as_ref());
+    let body = equipment.and_then(|eq| eq.body.as_ref());
+    scene.write(format!("Hand: {}  Body: {}",
+        hand.map_or("nothing".to_string(), describe_item),
+        body.map_or("nothing".to_string(), describe_item)), 0, 1);
+    let inventory = world.get::<Inventory>(*player_id).unwrap();
+    let mut load = format!("Inventory: {}/{} slots", inventory.items().count(), inventory.slots());
+    if let Some(max_weight) = inventory.max_weight() {
+        load.push_str(&format!(", weight {}/{}", inventory.weight(), max_weight));
+    }
+    if inventory.is_overloaded() {
+        load.push_str(" (slowed)");
+    }
+    scene.write(load, 0, 2);
+    for (row, (item, count)) in inventory.items().enumerate() {
+        scene.write(format!(" {}) {} x{}", row + 1, describe_item(item), count), 0, 3 + row as i32);
+    }
+    if let Some(trade) = world.trade_of(*player_id) {
+        render_trade(trade, *player_id, &mut scene);
+    }
+    scene
+}
+
+/// The trade window, in the top right corner.
+fn render_trade(trade: &Trade, player_id: EntityId, scene: &mut Scene) {
+    let side = trade.side(player_id).unwrap_or(0);
+    let mut lines = vec!["Trade".to_string()];
+    if !trade.open && side == 0 {
+        lines.push("Waiting for the other player...".to_string());
+        lines.push("n) Cancel".to_string());
+    } else if !trade.open {
+        lines.push("The other player wants to trade.".to_string());
+        lines.push("t) Agree  n) Decline".to_string());
+    } else {
+        for (title, offer, confirmed) in [("You offer", side, trade.confirmed[side]), ("They offer", 1 - side, trade.confirmed[1 - side])] {
+            lines.push(format!("{}:{}", title, if confirmed { " (confirmed)" } else { "" }));
+            let offer = &trade.offers[offer];
+            if offer.count() == 0 {
+                lines.push("  nothing".to_string());
             }
-            Some(RoofKind::Mountain) => {
-                scene.set_point(sx, sy, '#', AnsiValue::rgb(0, 0, 0), Some(AnsiValue::rgb(3, 1, 0)));
+            for (item, count) in offer.items() {
+                lines.push(format!("  {} x{}", describe_item(item), count));
             }
         }
+        lines.push("o) Offer  y) Confirm  n) Cancel".to_string());
+    }
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2;
+    let sx = terminal::SCREEN_W as i32 - width as i32;
+    for (row, line) in lines.into_iter().enumerate() {
+        scene.write(format!(" {:width$}", line, width = width - 1), sx, 1 + row as i32);
+    }
+}
+
+/// Shown instead of the world while the player waits to respawn.
+pub fn render_death_screen(ticks_left: u64) -> Box<Scene> {
+    let secs = (ticks_left * TICK_MILLIS).div_ceil(1000);
+    render_message(&["You died!".to_string(), format!("Respawning in {} s...", secs)])
+}
+
+/// A blank screen with the given lines centered on it.
+pub fn render_message(lines: &[String]) -> Box<Scene> {
+    let mut scene = Box::new(Scene::default());
+    for (row, line) in lines.iter().enumerate() {
+        let sx = (terminal::SCREEN_W as i32 - line.chars().count() as i32) / 2;
+        scene.write(line.clone(), sx, terminal::SCREEN_H as i32 / 2 - 1 + row as i32);
     }
-}
\ No newline at end of file
+    scene
+}
+
+fn describe_item(item: &Item) -> String {
+    match item.durability() {
+        Some(durability) => format!("{} ({})", item.kind().name(), durability),
+        None => item.kind().name().to_string(),
+    }
+}
+
+fn entity_glyph(kind: &EntityKind) -> (char, AnsiValue) {
+    match kind {
+        EntityKind::Player(_) => ('@', AnsiValue::rgb(5, 5, 5)),
+        EntityKind::Treasure => ('*', AnsiValue::rgb(5, 5, 0)),
+        EntityKind::Creature(kind) => (kind.def().glyph, color(kind.def().fg)),
+    }
+}
+
+fn color((r, g, b): (u8, u8, u8)) -> AnsiValue {
+    AnsiValue::rgb(r, g, b)
+}
+
+fn render_tile(tile: Tile, damage: u32, scene: &mut Scene, sx: i32, sy: i32) {
+    if let Some(ground) = tile.ground {
+        let def = ground.def();
+        scene.set_point(sx, sy, def.glyph, color(def.fg), def.bg.map(color));
+    }
+    let terrain = tile.terrain
+        .map(|terrain| terrain.def())
+        .map(|def| (def.glyph, color(def.fg), def.bg.map(color)));
+    if let Some((ch, foreground, background)) = terrain {
+        // Damaged terrain is drawn as cracked.
+        let (ch, foreground) = if damage > 0 { ('%', AnsiValue::rgb(5, 1, 0)) } else { (ch, foreground) };
+        scene.set_point(sx, sy, ch, foreground, background);
+    }
+}
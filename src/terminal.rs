@@ -36,6 +36,22 @@ impl Scene {
             self.0[sx][sy] = ColoredChar { ch, foreground, background }
         }
     }
+    /// Darken a point to the given fraction of its brightness; 0 blacks it out.
+    pub fn shade(&mut self, sx: i32, sy: i32, light: u8, max_light: u8) {
+        if !(sx < 0 || sy < 0 || sx >= SCREEN_W as i32 || sy >= SCREEN_H as i32) {
+            let point = &mut self.0[sx as usize][sy as usize];
+            point.foreground = shade_color(point.foreground, light, max_light);
+            point.background = shade_color(point.background, light, max_light);
+        }
+    }
+    /// Turn a point into dim shades of gray, for things remembered rather than seen.
+    pub fn mute(&mut self, sx: i32, sy: i32) {
+        if !(sx < 0 || sy < 0 || sx >= SCREEN_W as i32 || sy >= SCREEN_H as i32) {
+            let point = &mut self.0[sx as usize][sy as usize];
+            point.foreground = mute_color(point.foreground);
+            point.background = mute_color(point.background);
+        }
+    }
     pub fn write(&mut self, text: String, sx: i32, sy: i32) {
         for (ix, ch) in text.char_indices() {
             self.set_point(sx + ix as i32, sy, ch, AnsiValue::rgb(5, 5, 5), Some(AnsiValue::rgb(0, 0, 0)));
@@ -43,6 +59,32 @@ impl Scene {
     }
 }
 
+/// Scale a color from the 6x6x6 color cube or the grayscale ramp towards black.
+fn shade_color(color: AnsiValue, light: u8, max_light: u8) -> AnsiValue {
+    let scale = |c: u8| ((c as u32 * light as u32 + max_light as u32 / 2) / max_light as u32) as u8;
+    match color.0 {
+        16 ..= 231 => {
+            let c = color.0 - 16;
+            AnsiValue::rgb(scale(c / 36), scale(c / 6 % 6), scale(c % 6))
+        }
+        232 ..= 255 => AnsiValue::grayscale(scale(color.0 - 232)),
+        _ if light == 0 => AnsiValue::rgb(0, 0, 0),
+        _ => color,
+    }
+}
+
+/// A dark gray as bright as the given color's average channel.
+fn mute_color(color: AnsiValue) -> AnsiValue {
+    match color.0 {
+        16 ..= 231 => {
+            let c = color.0 - 16;
+            AnsiValue::grayscale((c / 36 + c / 6 % 6 + c % 6) * 2 / 3)
+        }
+        232 ..= 255 => AnsiValue::grayscale((color.0 - 232) / 2),
+        _ => color,
+    }
+}
+
 enum TerminalCommand {
     Println(String),
     SetQuery(Option<String>),
@@ -61,6 +103,11 @@ pub struct Terminal {
     control: channel::Sender<TerminalCommand>,
     input: channel::Sender<InputCommand>,
 }
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::new()
+    }
+}
 impl Terminal {
     pub fn new() -> Self {
         let (ttx, trx) = channel::unbounded();
@@ -190,8 +237,9 @@ impl TerminalState {
         let Scene(charmap) = self.scene;
         for y in 0 .. SCREEN_H as usize {
             print!("{}", termion::cursor::Goto(1+1, 1+1+y as u16));
-            for x in 0 .. SCREEN_W as usize {
-                print!("{}{}{}", charmap[x][y].foreground.fg_string(), charmap[x][y].background.bg_string(), charmap[x][y].ch);
+            for column in charmap.iter() {
+                let cell = column[y];
+                print!("{}{}{}", cell.foreground.fg_string(), cell.background.bg_string(), cell.ch);
             }
         }
         print!("{}{}", AnsiValue::rgb(5,5,5).fg_string(), AnsiValue::rgb(0, 0, 0).bg_string());
@@ -232,7 +280,7 @@ impl TerminalState {
         self.render_query();
     }
     fn finish_reply(&mut self, resp: channel::Sender<String>) {
-        resp.send(std::mem::replace(&mut self.reply, String::new())).unwrap();
+        resp.send(std::mem::take(&mut self.reply)).unwrap();
         self.query = None;
         self.render_query();
     }
@@ -1,62 +1,186 @@
 use rpds::RedBlackTreeMap as Map;
+use rpds::Vector;
 use archery::shared_pointer::kind::{ArcK, SharedPointerKind};
 use crate::ClientId;
 use crate::geom::*;
 use serde::{Serialize, Deserialize};
 use std::vec;
+use std::collections::VecDeque;
 use crate::level_loader;
+use crate::content::{content, ItemDef, GroundDef, TerrainDef};
+
+pub mod spatial;
+pub mod timers;
+pub mod rng;
+pub mod creatures;
+pub mod pathfinding;
+pub mod crafting;
+pub mod equipment;
+pub mod item_actions;
+pub mod building;
+pub mod survival;
+pub mod respawn;
+pub mod lighting;
+pub mod fov;
+pub mod exploration;
+pub mod generation;
+pub mod layers;
+pub mod combat;
+pub mod components;
+pub mod checksum;
+pub mod trading;
+use self::timers::TimerQueue;
+use self::rng::WorldRng;
+use self::creatures::{CreatureKind, Behaviour};
+use self::crafting::RecipeId;
+use self::equipment::{Equipment, EquipSlot};
+use self::exploration::ExploredMap;
+use self::combat::PvpZone;
+use self::trading::Trade;
+use self::components::{Components, AnyComponent};
+use self::survival::{Survival, SURVIVAL_INTERVAL, ATTACK_STAMINA};
+
+/// Length of a world tick in real time; the host advances the world clock at this rate.
+pub const TICK_MILLIS: u64 = 100;
 
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct EntityId(u64);
 
+/// Ids from here on are provisional. Clients give them to entities their own actions are predicted
+/// to create, until the host's events arrive with the real ids. The host never hands them out.
+const PROVISIONAL_IDS: u64 = 1 << 63;
+
 impl EntityId {
     fn next(self) -> Self {
         EntityId(self.0 + 1)
     }
+    pub fn is_provisional(self) -> bool {
+        self.0 >= PROVISIONAL_IDS
+    }
+}
+
+impl From<u64> for EntityId {
+    fn from(id: u64) -> EntityId {
+        EntityId(id)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct World {
-    pub entities: Map<EntityId, Entity, ArcK>,
+    components: Components,
     next_entity_id: EntityId,
+    next_provisional_id: EntityId,
     pub tiles : TileMap,
+    /// Seed of the terrain generator.
+    seed: u64,
+    time: u64,
+    timers: TimerQueue,
+    rng: WorldRng,
+    /// Damage dealt to tiles that have not broken yet.
+    tile_damage: Map<Vec, u32, ArcK>,
+    /// Clients whose player died, with the time at which they respawn.
+    dead_players: Map<ClientId, u64, ArcK>,
+    /// What each connected client's players have seen, kept across deaths and rejoins.
+    /// The host holds on to the maps of disconnected players.
+    explored: Map<ClientId, ExploredMap, ArcK>,
+    /// Whether players may fight each other, where no zone says otherwise.
+    pvp: bool,
+    /// Areas with their own PvP setting; the first one containing a position applies.
+    pvp_zones: Vector<PvpZone, ArcK>,
+    /// Trades in progress, keyed by the player who proposed them.
+    trades: Map<EntityId, Trade, ArcK>,
 }
 
 impl Default for World {
     fn default() -> World {
-        World {
-            entities : Map::new_with_ptr_kind(),
+        let seed = rand::random();
+        let mut world = World {
+            components : Default::default(),
             next_entity_id : EntityId(0),
-            tiles : level_loader::load_level(),
-        }
+            next_provisional_id : EntityId(PROVISIONAL_IDS),
+            tiles : generation::starting_tiles(seed),
+            seed,
+            time : 0,
+            timers : Default::default(),
+            rng : WorldRng::new(rand::random()),
+            tile_damage : Map::new_with_ptr_kind(),
+            dead_players : Map::new_with_ptr_kind(),
+            explored : Map::new_with_ptr_kind(),
+            pvp : false,
+            pvp_zones : combat::default_pvp_zones(),
+            trades : Map::new_with_ptr_kind(),
+        };
+        world.schedule(1, WorldEvent::SpawnCreatures);
+        world
     }
 }
 
+/// Everything an entity is made of, used to create it. Once in the world, each part is
+/// kept as a separate component. Optional components are added with `Entity::with`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub pos: Vec,
     pub kind: EntityKind,
-    pub hp: Option<(i64, i64)>,
-    pub inventory: Option<Inventory>,
+    components: vec::Vec<AnyComponent>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub hp: i64,
+    pub max: i64,
+}
+
+impl Health {
+    pub fn full(max: i64) -> Health {
+        Health { hp: max, max }
+    }
+}
+
+/// World times before which an entity may not act again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cooldowns {
+    pub next_move: u64,
+    pub next_attack: u64,
 }
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum EntityKind {
     Player(ClientId),
     Treasure, // items available to be picked up on the ground
+    Creature(CreatureKind),
 }
 
 impl Entity {
+    /// A freshly spawned player for `client`, with full health and an empty inventory.
+    pub fn player(client: ClientId, pos: Vec) -> Entity {
+        Entity::new(pos, EntityKind::Player(client))
+            .with(Health::full(10))
+            .with(Inventory::new(PLAYER_SLOTS, Some(PLAYER_MAX_WEIGHT)))
+            .with(Equipment::default())
+            .with(Cooldowns::default())
+            .with(Survival::default())
+    }
+    pub fn is_player(&self, client: ClientId) -> bool {
+        self.kind.is_player(client)
+    }
+}
+
+impl EntityKind {
     pub fn is_player(&self, client: ClientId) -> bool {
-        match self.kind {
-            EntityKind::Player(e_client) => e_client == client,
+        match self {
+            EntityKind::Player(e_client) => *e_client == client,
             _ => false
         }
     }
+    /// Whether walking over treasure moves its items into this entity's inventory.
+    pub fn picks_up_items(&self) -> bool {
+        matches!(self, EntityKind::Player(_))
+    }
     pub fn has_collision(&self) -> bool {
-        match self.kind {
+        match self {
             EntityKind::Player(_) => true,
             EntityKind::Treasure => false,
+            EntityKind::Creature(_) => true,
         }
     }
 }
@@ -64,56 +188,105 @@ impl Entity {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Inventory {
     items: vec::Vec<(Item, usize)>,
-    cap: usize,  // TODO
+    /// Maximum number of stacks.
+    slots: usize,
+    /// Weight above which the owner is slowed down, if any.
+    max_weight: Option<u32>,
 }
 
 impl Inventory {
+    pub fn new(slots: usize, max_weight: Option<u32>) -> Inventory {
+        Inventory { items: vec::Vec::new(), slots, max_weight }
+    }
     fn insert(&mut self, item: Item) -> bool {
-        if self.count() >= self.cap {
-            return false;
-        }
-        if item.kind.stacks() {
-            for (it, size) in self.items.iter_mut() {
-                if it.stacks_with(&item) {
-                    *size += 1;
-                    return true;
-                }
+        for (it, size) in self.items.iter_mut() {
+            if it.stacks_with(&item) && *size < item.kind.max_stack() {
+                *size += 1;
+                return true;
             }
         }
+        if self.items.len() >= self.slots {
+            return false;
+        }
         self.items.push((item, 1));
-        return true;
+        true
     }
-    fn insert_inventory(&mut self, other: &mut Inventory) {
-        while let Some((item, count)) = other.items.last_mut() {
-            if self.insert(item.clone()) {
+    /// Move as many items as fit from `other`, returning how many were moved.
+    fn insert_inventory(&mut self, other: &mut Inventory) -> usize {
+        let mut moved = 0;
+        for (item, count) in other.items.iter_mut() {
+            while *count > 0 && self.insert(item.clone()) {
                 *count -= 1;
-                if *count == 0 {
-                    other.items.pop();
-                }
-            }
-            else {
-                break;
+                moved += 1;
             }
         }
+        other.items.retain(|(_, count)| *count > 0);
+        moved
     }
     fn is_empty(&self) -> bool {
-        self.items.len() == 0
+        self.items.is_empty()
     }
     pub fn count(&self) -> usize {
         self.items.iter().map(|(_, ct)| ct).sum()
     }
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+    pub fn weight(&self) -> u32 {
+        self.items.iter().map(|(it, ct)| it.kind.weight() * *ct as u32).sum()
+    }
+    pub fn max_weight(&self) -> Option<u32> {
+        self.max_weight
+    }
+    pub fn is_overloaded(&self) -> bool {
+        self.max_weight.is_some_and(|max| self.weight() > max)
+    }
+    pub fn count_of(&self, kind: &ItemKind) -> usize {
+        self.items.iter().filter(|(it, _)| &it.kind == kind).map(|(_, ct)| ct).sum()
+    }
+    pub fn items(&self) -> impl Iterator<Item=(&Item, usize)> {
+        self.items.iter().map(|(it, ct)| (it, *ct))
+    }
+    fn get(&self, ix: usize) -> Option<&Item> {
+        self.items.get(ix).map(|(it, _)| it)
+    }
+    /// Take a single item out of stack `ix`.
+    fn take_one(&mut self, ix: usize) -> Option<Item> {
+        let (item, size) = self.items.get_mut(ix)?;
+        let item = item.clone();
+        *size -= 1;
+        if *size == 0 {
+            self.items.remove(ix);
+        }
+        Some(item)
+    }
+    /// Remove `n` items of the given kind, or nothing if there are fewer than `n`.
+    fn remove(&mut self, kind: &ItemKind, n: usize) -> bool {
+        if self.count_of(kind) < n {
+            return false;
+        }
+        let mut left = n;
+        for (it, size) in self.items.iter_mut().rev() {
+            if &it.kind == kind {
+                let taken = left.min(*size);
+                *size -= taken;
+                left -= taken;
+            }
+        }
+        self.items.retain(|(_, size)| *size > 0);
+        true
+    }
     fn drop(self, pos: Vec) -> WorldEvent {
-        WorldEvent::CreateEntity(Entity {
-            pos: pos,
-            kind: EntityKind::Treasure,
-            hp: None,
-            inventory: Some(self),
-        })
+        WorldEvent::CreateEntity(Entity::new(pos, EntityKind::Treasure).with(self))
     }
     fn of_item(item: Item) -> Inventory {
+        Inventory::of_stack(item, 1)
+    }
+    fn of_stack(item: Item, count: usize) -> Inventory {
         Inventory {
-            items: vec![(item, 1)],
-            cap: 1,
+            items: vec![(item, count)],
+            slots: 1,
+            max_weight: None,
         }
     }
 }
@@ -121,32 +294,71 @@ impl Inventory {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
     kind: ItemKind,
+    /// Uses left before a tool or weapon breaks.
+    durability: Option<u32>,
 }
 
 impl Item {
+    pub fn new(kind: ItemKind) -> Item {
+        let durability = kind.max_durability();
+        Item { kind, durability }
+    }
+    pub fn kind(&self) -> &ItemKind {
+        &self.kind
+    }
+    pub fn durability(&self) -> Option<u32> {
+        self.durability
+    }
     fn stacks_with(&self, it: &Item) -> bool {
         self.kind.stacks() && self.kind == it.kind
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-pub enum ItemKind {
-    Log,
-}
+/// A kind of item, as defined in the content file.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ItemKind(pub(crate) u16);
 
 impl ItemKind {
+    /// Every kind of item in the loaded content.
+    pub fn all() -> impl Iterator<Item=ItemKind> {
+        (0 .. content().items.len() as u16).map(ItemKind)
+    }
+    pub fn def(self) -> &'static ItemDef {
+        &content().items[self.0 as usize]
+    }
+    pub fn name(&self) -> &'static str {
+        &self.def().name
+    }
     fn stacks(&self) -> bool {
-        true
+        self.max_stack() > 1
+    }
+    pub fn max_stack(&self) -> usize {
+        self.def().stack
+    }
+    pub fn weight(&self) -> u32 {
+        self.def().weight
     }
 }
 
+pub const PLAYER_SLOTS: usize = 9;
+pub const PLAYER_MAX_WEIGHT: u32 = 60;
+/// Ticks an overloaded entity has to wait between steps.
+const OVERLOAD_MOVE_DELAY: u64 = 3;
+
 pub const CHUNK_SIZE: usize = 32;
 
 pub type Chunk = [[Tile; CHUNK_SIZE]; CHUNK_SIZE];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TileMap {
-    chunks: Map<(i32, i32), Chunk, ArcK>
+    /// Chunks keyed by layer and chunk coordinates.
+    chunks: Map<(i32, i32, i32), Chunk, ArcK>
+}
+
+impl Default for TileMap {
+    fn default() -> TileMap {
+        TileMap::new()
+    }
 }
 
 impl TileMap {
@@ -155,201 +367,545 @@ impl TileMap {
             chunks : Map::new_with_ptr_kind()
         }
     }
-    pub fn set_chunk(&mut self, cx: i32, cy: i32, ch: Chunk) {
-        self.chunks.insert_mut((cx, cy), ch);
+    pub fn set_chunk(&mut self, z: i32, cx: i32, cy: i32, ch: Chunk) {
+        self.chunks.insert_mut((z, cx, cy), ch);
+    }
+    pub fn has_chunk(&self, z: i32, cx: i32, cy: i32) -> bool {
+        self.chunks.contains_key(&(z, cx, cy))
+    }
+    pub fn chunk_coords(&self) -> impl Iterator<Item=(i32, i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+    /// Copy every tile of `other` that has ground over this map.
+    pub fn stamp(&mut self, other: &TileMap) {
+        for (&key, stamp) in other.chunks.iter() {
+            let mut chunk = self.chunks.get(&key).cloned().unwrap_or_default();
+            for (column, stamp_column) in chunk.iter_mut().zip(stamp.iter()) {
+                for (tile, stamp_tile) in column.iter_mut().zip(stamp_column.iter()) {
+                    if stamp_tile.ground.is_some() {
+                        *tile = stamp_tile.clone();
+                    }
+                }
+            }
+            self.chunks.insert_mut(key, chunk);
+        }
     }
-    fn conv_pos(pos: Vec) -> (i32, i32, usize, usize) {
+    fn conv_pos(pos: Vec) -> ((i32, i32, i32), usize, usize) {
         let cx = pos.x.div_euclid(CHUNK_SIZE as i32);
         let cy = pos.y.div_euclid(CHUNK_SIZE as i32);
         let px = pos.x.rem_euclid(CHUNK_SIZE as i32) as usize;
         let py = pos.y.rem_euclid(CHUNK_SIZE as i32) as usize;
-        (cx, cy, px, py)
+        ((pos.z, cx, cy), px, py)
     }
     pub fn get(&self, pos: Vec) -> Tile {
-        let (cx, cy, px, py) = TileMap::conv_pos(pos);
-        match self.chunks.get(&(cx, cy)) {
+        let (key, px, py) = TileMap::conv_pos(pos);
+        match self.chunks.get(&key) {
             Some(chunk) => chunk[px][py].clone(),
             None => Default::default(),
         }
     }
     pub fn set(&mut self, pos: Vec, tile: Tile) {
-        let (cx, cy, px, py) = TileMap::conv_pos(pos);
-        let mut chunk = match self.chunks.get(&(cx, cy)) {
+        let (key, px, py) = TileMap::conv_pos(pos);
+        let mut chunk = match self.chunks.get(&key) {
             Some(chunk) => chunk.clone(),
             None => Default::default(),
         };
         chunk[px][py] = tile;
-        self.chunks.insert_mut((cx, cy), chunk);
+        self.chunks.insert_mut(key, chunk);
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
 pub struct Tile {
     pub ground: Option<GroundKind>,
     pub terrain: Option<TerrainKind>,
-    pub roof: Option<RoofKind>,
 }
 
-impl Default for Tile {
-    fn default() -> Tile {
-        Tile {
-            ground: None,
-            terrain: None,
-            roof: None,
-        }
-    }
-}
 impl Tile {
-    fn is_free(self) -> bool {
-        self.ground != Some(GroundKind::Water) && (self.terrain == None || self.terrain == Some(TerrainKind::Entrance))
+    /// Whether entities can walk onto the tile.
+    pub fn is_free(&self) -> bool {
+        self.ground.as_ref().is_none_or(GroundKind::is_passable) && self.terrain.as_ref().is_none_or(TerrainKind::is_passable)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-pub enum GroundKind {
-    Grass, Rock, Water
-}
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-pub enum TerrainKind {
-    Tree, Cliff, Entrance
-}
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-pub enum RoofKind {
-    Mountain
+/// A kind of ground, as defined in the content file.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct GroundKind(pub(crate) u16);
+/// A kind of terrain, as defined in the content file.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct TerrainKind(pub(crate) u16);
+
+impl GroundKind {
+    pub fn def(self) -> &'static GroundDef {
+        &content().grounds[self.0 as usize]
+    }
+    /// Whether entities can walk on this ground.
+    pub fn is_passable(&self) -> bool {
+        self.def().passable
+    }
 }
 
+impl TerrainKind {
+    pub fn def(self) -> &'static TerrainDef {
+        &content().terrain[self.0 as usize]
+    }
+    /// Damage the terrain takes before breaking, or `None` if it cannot be broken.
+    pub fn hit_points(&self) -> Option<u32> {
+        self.def().hit_points
+    }
+    /// Item left behind when the terrain is broken.
+    pub fn drops(&self) -> Option<ItemKind> {
+        self.def().drops
+    }
+    /// Whether entities can walk through this terrain.
+    pub fn is_passable(&self) -> bool {
+        self.def().passable
+    }
+    pub fn is_entrance(&self) -> bool {
+        self.def().entrance
+    }
+    pub fn name(&self) -> &'static str {
+        &self.def().name
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PlayerActionEvent {
     Move(Dir),
     Attack(Dir),
+    Craft(RecipeId),
+    /// Equip the item in the given inventory slot.
+    Equip(usize),
+    Unequip(EquipSlot),
+    /// Drop up to a number of items from an inventory slot on the ground.
+    Drop(usize, usize),
+    /// Give up to a number of items from an inventory slot to the adjacent player in the given direction.
+    Give(usize, usize, Dir),
+    /// Move a number of items from an inventory slot into a new slot.
+    Split(usize, usize),
+    /// Merge the first inventory slot into the second.
+    Merge(usize, usize),
+    /// Place the item in the given inventory slot on the adjacent tile in the given direction.
+    Build(usize, Dir),
+    /// Eat one item from the given inventory slot.
+    Eat(usize),
+    /// Ask the adjacent player in the given direction to trade, or agree if they asked first.
+    Trade(Dir),
+    /// Put up to a number of items from an inventory slot into the open trade.
+    Offer(usize, usize),
+    /// Agree to the trade as it stands.
+    ConfirmTrade,
+    CancelTrade,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WorldEvent {
     PlayerAction(EntityId, PlayerActionEvent),
+    /// A new entity with its id, as assigned by `World::assign_id`.
     SpawnEntity(EntityId, Entity),
     DeleteEntity(EntityId),
+    /// A new entity that still needs an id; only `SpawnEntity` is ever applied.
     CreateEntity(Entity),
     Enter(EntityId, Vec),
+    /// Advance the world clock by one tick, firing any timers that are due.
+    Tick,
+    /// A creature's AI takes its next action.
+    CreatureThink(EntityId),
+    /// Try to spawn creatures around the players.
+    SpawnCreatures,
+    /// Periodic update of an entity's hunger, stamina and health.
+    SurvivalTick(EntityId),
+    /// A message for the player controlling the entity; does not change the world.
+    Notify(EntityId, String),
+    /// A dead player comes back to life.
+    Respawn(ClientId),
+    /// A client left while dead, so it should not respawn.
+    CancelRespawn(ClientId),
+    /// A disconnected client's explored map is dropped from the world; the host keeps it.
+    ForgetExplored(ClientId),
+    /// A client that reconnected gets back the map the host kept for its player.
+    RestoreExplored(ClientId, ExploredMap),
+    /// An entity came into a client's view, or turned out differently from what the client worked
+    /// out. It replaces whatever the client had under that id. Only the host sends these.
+    ShowEntity(EntityId, Entity),
+    /// An entity left a client's view. Unlike `DeleteEntity`, nothing else happens to it.
+    HideEntity(EntityId),
+    /// A tile in a client's view, with the damage it has taken, differs from the client's copy.
+    TileChanged(Vec, Tile, u32),
+    /// When the client's dead player respawns, if it is dead, as the host has it.
+    RespawnTime(ClientId, Option<u64>),
 }
 
-#[derive(Debug)]
+/// Why an event was rejected. The world is left unchanged when this happens.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum WorldError {
-    IllegalEvent,
+    /// The event refers to an entity that does not exist, for example one that died earlier in the same tick.
+    MissingEntity(EntityId),
+    /// An inventory slot, recipe or direction outside the valid range.
+    OutOfBounds,
+    /// The sender may not cause this event.
+    Unauthorized,
+    /// The event makes no sense, such as moving zero items.
+    InvalidState,
+}
+
+impl std::fmt::Display for WorldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WorldError::MissingEntity(id) => write!(f, "entity {:?} does not exist", id),
+            WorldError::OutOfBounds => write!(f, "index or direction out of bounds"),
+            WorldError::Unauthorized => write!(f, "sender may not cause this event"),
+            WorldError::InvalidState => write!(f, "event is not valid in this state"),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl WorldEvent {
+    /// The entity the event acts on, which has to exist for the event to be applied.
+    fn subject(&self) -> Option<EntityId> {
+        match self {
+            WorldEvent::PlayerAction(id, _) |
+            WorldEvent::DeleteEntity(id) |
+            WorldEvent::Enter(id, _) |
+            WorldEvent::CreatureThink(id) |
+            WorldEvent::SurvivalTick(id) => Some(*id),
+            _ => None,
+        }
+    }
 }
 
 impl World {
-    pub fn handle_event(&self, sender: Option<ClientId>, ev: WorldEvent) -> Result<(Self, vec::Vec<(u64, WorldEvent)>), WorldError> {
+    /// Apply an event, returning the new world and the events it causes immediately.
+    /// Events caused later are scheduled on the world's own timer queue.
+    pub fn handle_event(&self, sender: Option<ClientId>, ev: WorldEvent) -> Result<(Self, vec::Vec<WorldEvent>), WorldError> {
         let mut w = self.clone();
         let mut evs = vec::Vec::new();
         use WorldEvent::*;
+        if let Some(id) = ev.subject() {
+            w.require::<EntityKind>(id)?;
+        }
         match (sender, &ev) {
             (None, _) => {}
             (Some(client), PlayerAction(id, _)) =>
-                match w.entities.get(&id) {
-                    None => Err(WorldError::IllegalEvent)?, // trying to move nonexistent player -- unauthorized, fail
-                    Some(e) if e.is_player(client) => {} // authorized -- continue
-                    _ => Err(WorldError::IllegalEvent)? // trying to move entity other than self -- unauthorized, fail
+                if !w.require::<EntityKind>(*id)?.is_player(client) {
+                    Err(WorldError::Unauthorized)? // trying to move entity other than self
                 }
-            (Some(_), _) => Err(WorldError::IllegalEvent)?
+            (Some(_), _) => Err(WorldError::Unauthorized)?
+        }
+        if let PlayerAction(id, action) = &ev {
+            w.check_action(*id, action)?;
         }
         match ev {
             PlayerAction(id, PlayerActionEvent::Move(dir)) => {
-                let cur_pos = w.entities.get(&id).unwrap().pos;
-                let pos = cur_pos + dir.to_vec();
-                if w.is_free(pos) {
-                    if w.tiles.get(pos).roof == w.tiles.get(cur_pos).roof ||
-                       w.tiles.get(cur_pos).roof == None && w.tiles.get(pos).terrain == Some(TerrainKind::Entrance) ||
-                       w.tiles.get(pos).roof == None && w.tiles.get(cur_pos).terrain == Some(TerrainKind::Entrance) { 
-                        w.entities.modify(id, |player| player.pos += dir.to_vec());
-                        evs.push((0, Enter(id, pos)));
-                    }
+                w.try_move(&mut evs, id, dir)?;
+            }
+            PlayerAction(id, PlayerActionEvent::Attack(dir)) =>
+                w.attack(&mut evs, id, dir)?,
+            PlayerAction(id, PlayerActionEvent::Equip(ix)) =>
+                w.equip(id, ix)?,
+            PlayerAction(id, PlayerActionEvent::Unequip(slot)) =>
+                w.unequip(id, slot),
+            PlayerAction(id, PlayerActionEvent::Drop(ix, count)) =>
+                w.drop_items(&mut evs, id, ix, count)?,
+            PlayerAction(id, PlayerActionEvent::Give(ix, count, dir)) =>
+                w.give_items(id, ix, count, dir)?,
+            PlayerAction(id, PlayerActionEvent::Build(ix, dir)) =>
+                w.build(id, ix, dir)?,
+            PlayerAction(id, PlayerActionEvent::Split(ix, count)) =>
+                w.split_stack(&mut evs, id, ix, count)?,
+            PlayerAction(id, PlayerActionEvent::Merge(from, into)) =>
+                w.merge_stacks(id, from, into)?,
+            PlayerAction(id, PlayerActionEvent::Trade(dir)) =>
+                w.propose_trade(&mut evs, id, dir)?,
+            PlayerAction(id, PlayerActionEvent::Offer(ix, count)) =>
+                w.offer_items(&mut evs, id, ix, count)?,
+            PlayerAction(id, PlayerActionEvent::ConfirmTrade) =>
+                w.confirm_trade(&mut evs, id)?,
+            PlayerAction(id, PlayerActionEvent::CancelTrade) =>
+                if !w.cancel_trade(&mut evs, id) {
+                    Err(WorldError::InvalidState)?
+                },
+            PlayerAction(id, PlayerActionEvent::Craft(recipe)) => {
+                let recipe = recipe.recipe().ok_or(WorldError::OutOfBounds)?;
+                w.craft(id, recipe)?;
+            }
+            SpawnEntity(id, entity_data) => {
+                if w.get::<EntityKind>(id).is_some() {
+                    Err(WorldError::InvalidState)?
+                }
+                let pos = entity_data.pos;
+                if entity_data.get::<Behaviour>().is_some() {
+                    w.schedule(1, CreatureThink(id));
                 }
+                if entity_data.get::<Survival>().is_some() {
+                    w.schedule(SURVIVAL_INTERVAL, SurvivalTick(id));
+                }
+                w.insert_entity(id, entity_data);
+                if id.is_provisional() {
+                    w.next_provisional_id = w.next_provisional_id.max(id.next());
+                } else {
+                    w.next_entity_id = w.next_entity_id.max(id.next());
+                }
+                evs.push(Enter(id, pos));
             }
-            PlayerAction(id, PlayerActionEvent::Attack(dir)) => {
-                let cur_pos = w.entities.get(&id).unwrap().pos;
-                let attack_pos = cur_pos + dir.to_vec();
-                if w.tiles.get(attack_pos).roof == w.tiles.get(cur_pos).roof {
-                    for id in w.get_entities_at(attack_pos).map(|(id, _)| id).collect::<vec::Vec<_>>() {
-                        w.hurt(&mut evs, id, 1);
-                    }
-                    w.break_tile(&mut evs, attack_pos);
+            DeleteEntity(id) => {
+                w.cancel_trade(&mut evs, id);
+                w.remove_entity(id);
+            }
+            CreateEntity(_) =>
+                Err(WorldError::InvalidState)?,
+            Tick => {
+                w.time += 1;
+                evs.extend(w.timers.take_due(w.time));
+            }
+            Notify(_, _) => {}
+            SurvivalTick(id) =>
+                w.survival_tick(&mut evs, id)?,
+            PlayerAction(id, PlayerActionEvent::Eat(ix)) =>
+                w.eat(id, ix)?,
+            CreatureThink(id) =>
+                w.creature_think(&mut evs, id)?,
+            Respawn(client) =>
+                w.respawn(&mut evs, client),
+            CancelRespawn(client) => {
+                w.dead_players.remove_mut(&client);
+            }
+            ForgetExplored(client) => {
+                w.explored.remove_mut(&client);
+            }
+            RestoreExplored(client, explored) => {
+                w.explored.insert_mut(client, explored);
+            }
+            ShowEntity(id, entity) =>
+                w.insert_entity(id, entity),
+            HideEntity(id) =>
+                w.remove_entity(id),
+            TileChanged(pos, tile, damage) => {
+                // The rest of the chunk must not be left blank.
+                w.generate_chunk_at(pos);
+                w.tiles.set(pos, tile);
+                if damage > 0 {
+                    w.tile_damage.insert_mut(pos, damage);
+                } else {
+                    w.tile_damage.remove_mut(&pos);
                 }
             }
-            SpawnEntity(id, entity_data) =>
-                w.entities.insert_mut(id, entity_data),
-            DeleteEntity(id) =>
-                drop(w.entities.remove_mut(&id)),
-            CreateEntity(entity_data) => {
-                let id = w.next_entity_id;
-                let pos = entity_data.pos;
-                evs.push((0, SpawnEntity(id, entity_data)));
-                w.next_entity_id = w.next_entity_id.next();
-                evs.push((0, Enter(id, pos)));
+            RespawnTime(client, Some(at)) =>
+                w.dead_players.insert_mut(client, at),
+            RespawnTime(client, None) => {
+                w.dead_players.remove_mut(&client);
             }
+            SpawnCreatures =>
+                w.spawn_creatures(&mut evs),
             Enter(id, pos) => {
-                if let Some(inventory) = &w.entities.get(&id).unwrap().inventory {
+                let kind = *w.require::<EntityKind>(id)?;
+                if let EntityKind::Player(_) = kind {
+                    w.generate_near(pos);
+                }
+                w.explore(id);
+                if let (true, Some(inventory)) = (kind.picks_up_items(), w.get::<Inventory>(id)) {
                     let mut inventory = inventory.clone();
-                    for oid in w.get_entities_at(pos).map(|(id, _)| id).collect::<vec::Vec<_>>() {
-                        if oid != id {
-                            if let Some(o_inv) = &w.entities.get(&oid).unwrap().inventory {
-                                let mut o_inv = o_inv.clone();
-                                inventory.insert_inventory(&mut o_inv);
-                                if o_inv.is_empty() && w.entities.get(&oid).unwrap().kind == EntityKind::Treasure {
-                                    evs.push((0, DeleteEntity(oid)));
-                                }
-                                w.entities.modify(oid, |entity| entity.inventory = Some(o_inv));
-                            }
+                    let treasures = w.query_at::<(&EntityKind, &Inventory)>(pos)
+                        .filter(|(oid, (kind, _))| **kind == EntityKind::Treasure && *oid != id)
+                        .map(|(oid, (_, o_inv))| (oid, o_inv.clone()))
+                        .collect::<vec::Vec<_>>();
+                    let (mut picked, mut left) = (0, 0);
+                    for (oid, mut o_inv) in treasures {
+                        picked += inventory.insert_inventory(&mut o_inv);
+                        left += o_inv.count();
+                        if o_inv.is_empty() {
+                            evs.push(DeleteEntity(oid));
                         }
+                        w.set(oid, o_inv);
+                    }
+                    if left > 0 {
+                        evs.push(Notify(id, format!("Picked up {} items, {} did not fit.", picked, left)));
                     }
-                    w.entities.modify(id, |entity| entity.inventory = Some(inventory));
+                    w.set(id, inventory);
                 }
             }
         }
         Ok((w, evs))
     }
+    /// Reject player actions with arguments out of range before any of them is applied.
+    fn check_action(&self, id: EntityId, action: &PlayerActionEvent) -> Result<(), WorldError> {
+        use PlayerActionEvent::*;
+        let slots = self.get::<Inventory>(id).map_or(0, |inventory| inventory.items.len());
+        let slot = |ix: usize| if ix < slots { Ok(()) } else { Err(WorldError::OutOfBounds) };
+        let dir = |dir: &Dir| if Dir::all().contains(dir) { Ok(()) } else { Err(WorldError::OutOfBounds) };
+        match action {
+            Drop(_, 0) | Give(_, 0, _) | Split(_, 0) | Offer(_, 0) => Err(WorldError::InvalidState),
+            Move(d) | Attack(d) | Trade(d) => dir(d),
+            Craft(_) | Unequip(_) | ConfirmTrade | CancelTrade => Ok(()),
+            Equip(ix) | Drop(ix, _) | Split(ix, _) | Eat(ix) | Offer(ix, _) => slot(*ix),
+            Give(ix, _, d) | Build(ix, d) => slot(*ix).and_then(|_| dir(d)),
+            Merge(from, into) => slot(*from).and_then(|_| slot(*into)),
+        }
+    }
+    /// Turn a `CreateEntity` into a `SpawnEntity` with the next free id; other events are returned
+    /// as they are. The host assigns the real ids. Clients predicting the outcome of their own
+    /// actions use provisional ones, which never clash with the host's.
+    pub fn assign_id(&self, ev: WorldEvent, provisional: bool) -> WorldEvent {
+        match ev {
+            WorldEvent::CreateEntity(entity) => {
+                let id = if provisional { self.next_provisional_id } else { self.next_entity_id };
+                WorldEvent::SpawnEntity(id, entity)
+            }
+            ev => ev,
+        }
+    }
+    /// Apply an event along with everything it causes, the way a client replaying the host's
+    /// follow-ups would. Follow-ups the world rejects are skipped; only an error in `ev` itself is
+    /// returned. Entities get provisional ids when `provisional` is set, as in `assign_id`.
+    pub fn apply(&self, sender: Option<ClientId>, ev: WorldEvent, provisional: bool) -> Result<World, WorldError> {
+        let (mut world, evs) = self.handle_event(sender, self.assign_id(ev, provisional))?;
+        let mut follow_ups = VecDeque::from(evs);
+        while let Some(ev) = follow_ups.pop_front() {
+            let ev = world.assign_id(ev, provisional);
+            if let Ok((next_world, evs)) = world.handle_event(None, ev) {
+                world = next_world;
+                follow_ups.extend(evs);
+            }
+        }
+        Ok(world)
+    }
     pub fn create_player_spawn_event(&self, id: ClientId) -> WorldEvent {
-        WorldEvent::CreateEntity(Entity {
-            pos: Vec::new(0, 0),
-            kind: EntityKind::Player(id),
-            hp: Some((10, 10)),
-            inventory: Some(Inventory { items: vec::Vec::new(), cap: 64 }),
-        })
+        WorldEvent::CreateEntity(Entity::player(id, Vec::new(0, 0)))
+    }
+    /// Whether the client has a player in the world, alive or waiting to respawn.
+    pub fn has_player(&self, id: ClientId) -> bool {
+        self.respawn_time(id).is_some() || self.player_entity(id).is_some()
+    }
+    /// The entity a client is playing, if it is alive.
+    pub fn player_entity(&self, client: ClientId) -> Option<EntityId> {
+        self.query::<&EntityKind>()
+            .find(|(_, kind)| kind.is_player(client))
+            .map(|(id, _)| id)
     }
     pub fn create_player_exit_event(&self, id: ClientId) -> Option<WorldEvent> {
-        self.entities.iter()
-            .find(|(_eid, entity)| entity.is_player(id))
-            .map(|(eid, _)| WorldEvent::DeleteEntity(*eid))
+        self.player_entity(id)
+            .map(WorldEvent::DeleteEntity)
+            .or_else(|| self.respawn_time(id).map(|_| WorldEvent::CancelRespawn(id)))
     }
-    pub fn get_entities_at(&self, pos: Vec) -> impl Iterator<Item=(EntityId, &Entity)> {
-        self.entities.iter().filter(move |(_, ent)| ent.pos == pos).map(|(eid, ent)| (*eid, ent))
+    /// The world as known to a client that has not seen anything yet: the map, the time and the
+    /// rules, without any entities, trades, deaths, timers or explored maps.
+    pub fn public_view(&self) -> World {
+        World {
+            components: Default::default(),
+            timers: Default::default(),
+            dead_players: Map::new_with_ptr_kind(),
+            explored: Map::new_with_ptr_kind(),
+            trades: Map::new_with_ptr_kind(),
+            ..self.clone()
+        }
     }
-    fn hurt(&mut self, evs: &mut vec::Vec<(u64, WorldEvent)>, id: EntityId, dmg: i64) {
-        match self.entities.get(&id).unwrap().hp {
+    /// Current world time, in ticks.
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+    /// Fire `ev` after `delay` ticks have passed.
+    pub fn schedule(&mut self, delay: u64, ev: WorldEvent) {
+        self.timers.schedule(self.time + delay, ev);
+    }
+    /// Take `dmg` hit points from an entity, killing it once they run out. Entities that are
+    /// already dead, but not deleted yet, are left alone so they do not die twice.
+    fn hurt(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, dmg: i64) -> Result<(), WorldError> {
+        match self.get::<Health>(id).copied() {
             None => {}
-            Some((mut hp, max)) => {
-                hp -= dmg;
-                self.entities.modify(id, |ent| ent.hp = Some((hp, max)));
+            Some(Health { hp, .. }) if hp <= 0 => {}
+            Some(Health { hp, max }) => {
+                let (pos, kind) = (*self.require::<Vec>(id)?, *self.require::<EntityKind>(id)?);
+                let hp = hp - dmg;
+                self.set(id, Health { hp, max });
                 if hp <= 0 {
-                    evs.push((0, WorldEvent::DeleteEntity(id)));
+                    match kind {
+                        EntityKind::Player(client) => return self.kill_player(evs, id, client),
+                        EntityKind::Creature(kind) => evs.push(kind.drop_loot(pos)),
+                        EntityKind::Treasure => {}
+                    }
+                    evs.push(WorldEvent::DeleteEntity(id));
                 }
             }
         }
+        Ok(())
     }
     fn is_free(&self, pos: Vec) -> bool {
-        self.tiles.get(pos).is_free() && self.get_entities_at(pos).filter(|(_, ent)| ent.has_collision()).next().is_none()
+        self.tiles.get(pos).is_free() && !self.has_collider_at(pos)
     }
-    fn break_tile(&mut self, evs: &mut vec::Vec<(u64, WorldEvent)>, pos: Vec) {
-        let mut tile = self.tiles.get(pos);
-        match tile.terrain {
-            None => {}
-            Some(TerrainKind::Tree) => {
-                tile.terrain = None;
-                evs.push((0, Inventory::of_item(Item { kind: ItemKind::Log }).drop(pos)));
+    fn has_collider_at(&self, pos: Vec) -> bool {
+        self.query_at::<&EntityKind>(pos).any(|(_, kind)| kind.has_collision())
+    }
+    /// Whether the terrain allows stepping from `from` onto the neighbouring tile `to`, ignoring entities.
+    /// Layers are only connected through entrances, which `try_move` handles.
+    fn tile_step(&self, from: Vec, to: Vec) -> bool {
+        from.z == to.z && self.tiles.get(to).is_free()
+    }
+    /// Whether something standing on `from` may step onto the neighbouring tile `to` right now.
+    fn can_enter(&self, from: Vec, to: Vec) -> bool {
+        self.tile_step(from, to) && !self.has_collider_at(to)
+    }
+    fn try_move(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, dir: Dir) -> Result<bool, WorldError> {
+        let cur_pos = *self.require::<Vec>(id)?;
+        let pos = cur_pos + dir.to_vec();
+        if self.get::<Cooldowns>(id).is_some_and(|cd| self.time < cd.next_move) {
+            return Ok(false);
+        }
+        if !self.can_enter(cur_pos, pos) {
+            return Ok(false);
+        }
+        if self.get::<Inventory>(id).is_some_and(Inventory::is_overloaded) {
+            let next_move = self.time + OVERLOAD_MOVE_DELAY;
+            self.modify(id, |cd: &mut Cooldowns| cd.next_move = next_move);
+        }
+        self.step_onto(evs, id, pos)?;
+        Ok(true)
+    }
+    /// Move an entity onto the neighbouring tile `pos`, which `can_enter` allows, however it got there.
+    /// Stepping onto an entrance leads through to the other layer, unless the way is blocked.
+    fn step_onto(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, mut pos: Vec) -> Result<(), WorldError> {
+        if let Some(other) = self.linked_entrance(pos) {
+            if !self.has_collider_at(other) {
+                pos = other;
             }
-            Some(_) => {}
+        }
+        self.move_entity(id, pos)?;
+        evs.push(WorldEvent::Enter(id, pos));
+        // Trades are only between neighbours.
+        self.cancel_trade(evs, id);
+        Ok(())
+    }
+    /// Damage at `pos` that has not broken the tile yet.
+    pub fn tile_damage(&self, pos: Vec) -> u32 {
+        self.tile_damage.get(&pos).copied().unwrap_or(0)
+    }
+    /// Damage the terrain at `pos`, breaking it once its hit points run out.
+    /// Returns whether the hit had any effect.
+    fn break_tile(&mut self, evs: &mut vec::Vec<WorldEvent>, pos: Vec, power: u32) -> bool {
+        let mut tile = self.tiles.get(pos);
+        let terrain = match tile.terrain {
+            Some(terrain) => terrain,
+            None => return false,
+        };
+        let hit_points = match terrain.hit_points() {
+            Some(hp) if power > 0 => hp,
+            _ => return false,
+        };
+        let damage = self.tile_damage(pos) + power;
+        if damage < hit_points {
+            self.tile_damage.insert_mut(pos, damage);
+            return true;
+        }
+        self.tile_damage.remove_mut(&pos);
+        tile.terrain = None;
+        if let Some(ground) = terrain.def().leaves {
+            tile.ground = Some(ground);
+        }
+        if let Some(item) = terrain.drops() {
+            evs.push(Inventory::of_item(Item::new(item)).drop(pos));
         }
         self.tiles.set(pos, tile);
+        true
     }
 }
 
@@ -363,8 +919,10 @@ impl<K: Ord, V: Clone, P: SharedPointerKind> MapExt for Map<K, V, P> {
     type K = K;
     type V = V;
     fn modify<F: FnOnce(&mut V)>(&mut self, key: K, f: F) {
-        let mut value = self.get(&key).unwrap().clone();
-        f(&mut value);
-        self.insert_mut(key, value);
+        if let Some(value) = self.get(&key) {
+            let mut value = value.clone();
+            f(&mut value);
+            self.insert_mut(key, value);
+        }
     }
 }
@@ -0,0 +1,54 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+/// What placing an item does to the tile it is placed on.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Placement {
+    Terrain(TerrainKind),
+    Ground(GroundKind),
+}
+
+impl ItemKind {
+    pub fn placement(&self) -> Option<Placement> {
+        self.def().places.clone()
+    }
+}
+
+impl World {
+    /// Whether `placement` may go on the tile at `pos`.
+    fn can_place(&self, pos: Vec, placement: &Placement) -> bool {
+        let tile = self.tiles.get(pos);
+        if tile.terrain.is_some() || tile.ground.is_none() {
+            return false;
+        }
+        match placement {
+            // Nothing may be walled in, not even items on the ground.
+            Placement::Terrain(_) =>
+                tile.ground.as_ref().is_some_and(GroundKind::is_passable) && self.query_at::<&EntityKind>(pos).next().is_none(),
+            // Floors can be laid over water to bridge it.
+            Placement::Ground(ground) =>
+                tile.ground != Some(*ground),
+        }
+    }
+    /// Place the item from inventory slot `ix` on the tile next to the entity in `dir`.
+    pub(super) fn build(&mut self, id: EntityId, ix: usize, dir: Dir) -> Result<(), WorldError> {
+        let pos = *self.require::<Vec>(id)? + dir.to_vec();
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        let placement = match inventory.get(ix).and_then(|item| item.kind.placement()) {
+            Some(placement) => placement,
+            None => return Ok(()),
+        };
+        if !self.can_place(pos, &placement) {
+            return Ok(());
+        }
+        inventory.take_one(ix);
+        let mut tile = self.tiles.get(pos);
+        match placement {
+            Placement::Terrain(terrain) => tile.terrain = Some(terrain),
+            Placement::Ground(ground) => tile.ground = Some(ground),
+        }
+        self.tiles.set(pos, tile);
+        self.set(id, inventory);
+        Ok(())
+    }
+}
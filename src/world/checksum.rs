@@ -0,0 +1,38 @@
+use std::io;
+use super::*;
+
+/// Ticks between two checksums sent by the host, so clients notice a desync within a few seconds.
+pub const CHECKSUM_INTERVAL: u64 = 50;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a over everything written to it.
+struct Fnv(u64);
+
+impl io::Write for Fnv {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl World {
+    /// Hash of the whole world state. Worlds that went through the same events have the same
+    /// checksum on every machine, since all maps in the world are ordered.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = Fnv(FNV_OFFSET);
+        // Writing into the hasher cannot fail, and neither can serializing the world.
+        let _ = bincode::serialize_into(&mut hasher, self);
+        hasher.0
+    }
+    /// Whether the host should send a checksum after this tick.
+    pub fn is_checksum_tick(&self) -> bool {
+        self.time.is_multiple_of(CHECKSUM_INTERVAL)
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Serialize, Deserialize};
+use rpds::Vector;
+use std::vec;
+use super::*;
+
+/// Ticks an entity has to wait between two attacks.
+pub const ATTACK_COOLDOWN: u64 = 5;
+
+/// An area where the server-wide PvP setting is overridden.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PvpZone {
+    /// Corners of the zone, inclusive, on a single layer.
+    pub min: Vec,
+    pub max: Vec,
+    pub pvp: bool,
+}
+
+impl PvpZone {
+    pub fn contains(&self, pos: Vec) -> bool {
+        pos.z == self.min.z
+            && self.min.x <= pos.x && pos.x <= self.max.x
+            && self.min.y <= pos.y && pos.y <= self.max.y
+    }
+}
+
+/// The zones every world starts with, as set up in the content file.
+pub(super) fn default_pvp_zones() -> Vector<PvpZone, ArcK> {
+    let mut zones = Vector::new_with_ptr_kind();
+    for zone in &content().pvp_zones {
+        zones.push_back_mut(zone.clone());
+    }
+    zones
+}
+
+impl EntityKind {
+    /// How combat messages refer to an entity of this kind.
+    fn describe(&self) -> String {
+        match self {
+            EntityKind::Player(_) => "another player".to_string(),
+            EntityKind::Treasure => "the treasure".to_string(),
+            EntityKind::Creature(kind) => format!("the {}", kind.def().name),
+        }
+    }
+}
+
+impl World {
+    /// Set whether players may fight each other outside of zones that say otherwise.
+    pub fn with_pvp(mut self, pvp: bool) -> World {
+        self.pvp = pvp;
+        self
+    }
+    /// Whether players may hurt each other at `pos`.
+    pub fn pvp_at(&self, pos: Vec) -> bool {
+        self.pvp_zones.iter()
+            .find(|zone| zone.contains(pos))
+            .map_or(self.pvp, |zone| zone.pvp)
+    }
+    /// A player hits whatever is on the adjacent tile in the given direction.
+    pub(super) fn attack(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, dir: Dir) -> Result<(), WorldError> {
+        if self.get::<Cooldowns>(id).is_some_and(|cd| self.time < cd.next_attack) {
+            return Ok(());
+        }
+        let cur_pos = *self.require::<Vec>(id)?;
+        let attack_pos = cur_pos + dir.to_vec();
+        let pvp = self.pvp_at(cur_pos) && self.pvp_at(attack_pos);
+        let mut protected = false;
+        let targets = self.query_at::<(&EntityKind, &Health)>(attack_pos)
+            .filter(|(_, (kind, _))| {
+                let allowed = pvp || !matches!(kind, EntityKind::Player(_));
+                protected |= !allowed;
+                allowed
+            })
+            .map(|(id, _)| id)
+            .collect::<vec::Vec<_>>();
+        let terrain = self.tiles.get(attack_pos).terrain;
+        if protected {
+            evs.push(WorldEvent::Notify(id, "You cannot fight other players here.".to_string()));
+            if targets.is_empty() && terrain.is_none() {
+                return Ok(());
+            }
+        }
+        if !self.use_stamina(id, ATTACK_STAMINA) {
+            return Ok(());
+        }
+        let next_attack = self.time + ATTACK_COOLDOWN;
+        self.modify(id, |cd: &mut Cooldowns| cd.next_attack = next_attack);
+        let equipment = self.equipment_of(id);
+        let mut used = !targets.is_empty();
+        for target in targets {
+            self.strike(evs, id, target, equipment.damage(), dir)?;
+        }
+        if let Some(terrain) = terrain {
+            used |= self.break_tile(evs, attack_pos, equipment.break_power(&terrain));
+        }
+        if used {
+            self.wear(id, EquipSlot::Hand);
+        }
+        Ok(())
+    }
+    /// One entity hits another, which is knocked back one tile in `dir` if it survives and there is room.
+    /// Being knocked onto an entrance leads through it, as stepping onto one does.
+    /// Hitting an entity that is already dead does nothing.
+    pub(super) fn strike(&mut self, evs: &mut vec::Vec<WorldEvent>, attacker: EntityId, target: EntityId, damage: i64, dir: Dir) -> Result<(), WorldError> {
+        let attacker_kind = *self.require::<EntityKind>(attacker)?;
+        if self.get::<Health>(target).is_some_and(|health| health.hp <= 0) {
+            return Ok(());
+        }
+        let (target_kind, target_pos) = (*self.require::<EntityKind>(target)?, *self.require::<Vec>(target)?);
+        let defense = self.equipment_of(target).defense();
+        // Armour softens blows, but never makes an entity untouchable.
+        let damage = if damage > 0 { (damage - defense).max(1) } else { 0 };
+        if defense > 0 {
+            self.wear(target, EquipSlot::Body);
+        }
+        // Tell the players first, so a dying player still gets the message.
+        let killed = self.get::<Health>(target).is_some_and(|health| health.hp <= damage);
+        if let EntityKind::Player(_) = attacker_kind {
+            let text = if killed {
+                format!("You killed {}.", target_kind.describe())
+            } else {
+                format!("You hit {} for {} damage.", target_kind.describe(), damage)
+            };
+            evs.push(WorldEvent::Notify(attacker, text));
+        }
+        if let EntityKind::Player(_) = target_kind {
+            let text = if killed {
+                format!("You were killed by {}.", attacker_kind.describe())
+            } else {
+                format!("You were hit by {} for {} damage.", attacker_kind.describe(), damage)
+            };
+            evs.push(WorldEvent::Notify(target, text));
+        }
+        self.hurt(evs, target, damage)?;
+        let knocked_to = target_pos + dir.to_vec();
+        if !killed && self.can_enter(target_pos, knocked_to) {
+            self.step_onto(evs, target, knocked_to)?;
+        }
+        Ok(())
+    }
+}
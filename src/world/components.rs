@@ -0,0 +1,271 @@
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use super::*;
+use super::spatial::SpatialIndex;
+
+/// Persistent map holding one type of component for every entity that has it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Store<T> {
+    map: Map<EntityId, T, ArcK>,
+}
+
+impl<T> Default for Store<T> {
+    fn default() -> Store<T> {
+        Store { map: Map::new_with_ptr_kind() }
+    }
+}
+
+impl<T: Clone> Store<T> {
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        self.map.get(&id)
+    }
+    pub fn iter(&self) -> impl Iterator<Item=(EntityId, &T)> {
+        self.map.iter().map(|(id, value)| (*id, value))
+    }
+    fn insert(&mut self, id: EntityId, value: T) {
+        self.map.insert_mut(id, value);
+    }
+    fn remove(&mut self, id: EntityId) {
+        self.map.remove_mut(&id);
+    }
+    /// Change the component in place; entities without it are left alone.
+    fn modify<F: FnOnce(&mut T)>(&mut self, id: EntityId, f: F) {
+        self.map.modify(id, f);
+    }
+}
+
+/// A type that entities can have, stored in its own map on the world.
+pub trait Component: Clone {
+    fn store(components: &Components) -> &Store<Self>;
+    fn store_mut(components: &mut Components) -> &mut Store<Self>;
+}
+
+/// The component stores, plus an index of which chunk each entity is in. The index is
+/// derived from the positions, so it is left out when serializing and rebuilt on load.
+#[derive(Debug, Clone, Default)]
+pub struct Components {
+    stores: Stores,
+    index: SpatialIndex,
+}
+
+impl Serialize for Components {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.stores.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Components {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Components, D::Error> {
+        let stores = Stores::deserialize(deserializer)?;
+        let mut index = SpatialIndex::default();
+        for (id, pos) in stores.positions.iter() {
+            index.insert(id, *pos);
+        }
+        Ok(Components { stores, index })
+    }
+}
+
+macro_rules! component {
+    ($ty:ty, $field:ident) => {
+        impl Component for $ty {
+            fn store(components: &Components) -> &Store<Self> {
+                &components.stores.$field
+            }
+            fn store_mut(components: &mut Components) -> &mut Store<Self> {
+                &mut components.stores.$field
+            }
+        }
+    };
+}
+
+/// Declares the optional components: their stores, their variant of `AnyComponent`, and
+/// how entities are split into them. Adding a component only takes a line here.
+macro_rules! optional_components {
+    ($($ty:ident => $field:ident),* $(,)?) => {
+        /// All component stores. Every entity has a position and a kind; everything else is optional.
+        #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+        struct Stores {
+            positions: Store<Vec>,
+            kinds: Store<EntityKind>,
+            $($field: Store<$ty>,)*
+        }
+
+        /// Any one of the optional components, as carried by an `Entity` before it is spawned.
+        #[derive(Debug, Clone, Serialize, Deserialize)]
+        pub enum AnyComponent {
+            $($ty($ty),)*
+        }
+
+        $(
+            component!($ty, $field);
+
+            impl OptionalComponent for $ty {
+                fn wrap(self) -> AnyComponent {
+                    AnyComponent::$ty(self)
+                }
+                fn unwrap(any: &AnyComponent) -> Option<&Self> {
+                    match any {
+                        AnyComponent::$ty(value) => Some(value),
+                        #[allow(unreachable_patterns)]
+                        _ => None,
+                    }
+                }
+            }
+        )*
+
+        impl Stores {
+            /// Split an entity into its components, replacing any it had before.
+            fn insert(&mut self, id: EntityId, entity: Entity) {
+                self.remove(id);
+                self.positions.insert(id, entity.pos);
+                self.kinds.insert(id, entity.kind);
+                for component in entity.components {
+                    match component {
+                        $(AnyComponent::$ty(value) => self.$field.insert(id, value),)*
+                    }
+                }
+            }
+            /// Gather an entity's components back together.
+            fn extract(&self, id: EntityId) -> Option<Entity> {
+                let mut entity = Entity::new(*self.positions.get(id)?, *self.kinds.get(id)?);
+                $(if let Some(value) = self.$field.get(id) {
+                    entity.components.push(value.clone().wrap());
+                })*
+                Some(entity)
+            }
+            fn remove(&mut self, id: EntityId) {
+                self.positions.remove(id);
+                self.kinds.remove(id);
+                $(self.$field.remove(id);)*
+            }
+        }
+    };
+}
+
+/// A component an entity may be created with, besides its position and kind.
+pub trait OptionalComponent: Component {
+    fn wrap(self) -> AnyComponent;
+    fn unwrap(any: &AnyComponent) -> Option<&Self>;
+}
+
+component!(Vec, positions);
+component!(EntityKind, kinds);
+
+optional_components! {
+    Health => health,
+    Inventory => inventories,
+    Behaviour => behaviours,
+    Equipment => equipment,
+    Cooldowns => cooldowns,
+    Survival => survival,
+}
+
+impl Entity {
+    pub fn new(pos: Vec, kind: EntityKind) -> Entity {
+        Entity { pos, kind, components: vec::Vec::new() }
+    }
+    /// Give the entity a component, replacing one of the same type.
+    pub fn with<T: OptionalComponent>(mut self, value: T) -> Entity {
+        self.components.retain(|any| T::unwrap(any).is_none());
+        self.components.push(value.wrap());
+        self
+    }
+    pub fn get<T: OptionalComponent>(&self) -> Option<&T> {
+        self.components.iter().find_map(T::unwrap)
+    }
+}
+
+/// A combination of components looked up together, such as `(&Vec, &Health)`.
+/// `Option<&T>` also matches entities without a `T`.
+pub trait Query<'a>: Sized {
+    fn fetch(components: &'a Components, id: EntityId) -> Option<Self>;
+}
+
+impl<'a, T: Component> Query<'a> for &'a T {
+    fn fetch(components: &'a Components, id: EntityId) -> Option<Self> {
+        T::store(components).get(id)
+    }
+}
+
+impl<'a, T: Component> Query<'a> for Option<&'a T> {
+    fn fetch(components: &'a Components, id: EntityId) -> Option<Self> {
+        Some(T::store(components).get(id))
+    }
+}
+
+macro_rules! tuple_query {
+    ($($name:ident),*) => {
+        impl<'a, $($name: Query<'a>),*> Query<'a> for ($($name,)*) {
+            fn fetch(components: &'a Components, id: EntityId) -> Option<Self> {
+                Some(($($name::fetch(components, id)?,)*))
+            }
+        }
+    };
+}
+
+tuple_query!(A);
+tuple_query!(A, B);
+tuple_query!(A, B, C);
+tuple_query!(A, B, C, D);
+
+impl World {
+    pub fn get<T: Component>(&self, id: EntityId) -> Option<&T> {
+        T::store(&self.components).get(id)
+    }
+    /// Like `get`, for components the entity cannot do without.
+    pub fn require<T: Component>(&self, id: EntityId) -> Result<&T, WorldError> {
+        self.get::<T>(id).ok_or(WorldError::MissingEntity(id))
+    }
+    /// All entities with the queried components, in id order.
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> impl Iterator<Item=(EntityId, Q)> + 'a {
+        self.components.stores.kinds.iter()
+            .filter_map(move |(id, _)| Q::fetch(&self.components, id).map(|q| (id, q)))
+    }
+    pub fn query_at<'a, Q: Query<'a>>(&'a self, pos: Vec) -> impl Iterator<Item=(EntityId, Q)> + 'a {
+        self.query_in(pos, pos)
+    }
+    /// Entities with the queried components inside the box spanned by `min` and `max`, inclusive on all sides.
+    pub fn query_in<'a, Q: Query<'a>>(&'a self, min: Vec, max: Vec) -> impl Iterator<Item=(EntityId, Q)> + 'a {
+        self.components.index.candidates(min, max)
+            .filter(move |id| self.get::<Vec>(*id).is_some_and(|pos|
+                min.x <= pos.x && pos.x <= max.x && min.y <= pos.y && pos.y <= max.y && min.z <= pos.z && pos.z <= max.z))
+            .filter_map(move |id| Q::fetch(&self.components, id).map(|q| (id, q)))
+    }
+    /// Entities with the queried components within euclidean distance `radius` of `center`.
+    pub fn query_near<'a, Q: Query<'a>>(&'a self, center: Vec, radius: i32) -> impl Iterator<Item=(EntityId, Q)> + 'a {
+        let span = Vec::new(radius, radius);
+        self.query_in::<(&Vec, Q)>(center - span, center + span)
+            .filter(move |(_, (pos, _))| (**pos - center).len_sq() <= radius * radius)
+            .map(|(id, (_, q))| (id, q))
+    }
+    /// Give an entity a component, replacing the one it had. Positions change through `move_entity`.
+    pub(super) fn set<T: Component>(&mut self, id: EntityId, value: T) {
+        T::store_mut(&mut self.components).insert(id, value);
+    }
+    /// Change an entity's component in place, if it has one.
+    pub(super) fn modify<T: Component, F: FnOnce(&mut T)>(&mut self, id: EntityId, f: F) {
+        T::store_mut(&mut self.components).modify(id, f);
+    }
+    /// A copy of the entity with all its components, as it could be spawned again.
+    pub fn entity(&self, id: EntityId) -> Option<Entity> {
+        self.components.stores.extract(id)
+    }
+    pub(super) fn insert_entity(&mut self, id: EntityId, entity: Entity) {
+        if let Some(old) = self.get::<Vec>(id) {
+            self.components.index.remove(id, *old);
+        }
+        self.components.index.insert(id, entity.pos);
+        self.components.stores.insert(id, entity);
+    }
+    pub(super) fn remove_entity(&mut self, id: EntityId) {
+        if let Some(old) = self.get::<Vec>(id) {
+            self.components.index.remove(id, *old);
+            self.components.stores.remove(id);
+        }
+    }
+    pub(super) fn move_entity(&mut self, id: EntityId, pos: Vec) -> Result<(), WorldError> {
+        let from = *self.require::<Vec>(id)?;
+        self.components.index.relocate(id, from, pos);
+        self.components.stores.positions.insert(id, pos);
+        Ok(())
+    }
+}
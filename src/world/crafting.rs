@@ -0,0 +1,80 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+use crate::content::content;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct RecipeId(pub usize);
+
+/// Turns a set of items into another, optionally only next to a crafting station.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    pub inputs: std::vec::Vec<(ItemKind, usize)>,
+    pub outputs: std::vec::Vec<(ItemKind, usize)>,
+    /// Terrain that must be adjacent to the crafter.
+    pub station: Option<TerrainKind>,
+}
+
+impl RecipeId {
+    pub fn recipe(self) -> Option<&'static Recipe> {
+        content().recipes.get(self.0)
+    }
+}
+
+impl Recipe {
+    /// Human-readable summary, e.g. "1 log -> 2 plank".
+    pub fn describe(&self) -> String {
+        fn list(items: &[(ItemKind, usize)]) -> String {
+            items.iter()
+                .map(|(kind, n)| format!("{} {}", n, kind.name()))
+                .collect::<std::vec::Vec<_>>()
+                .join(", ")
+        }
+        let mut name = self.name.chars();
+        let name = name.next().map_or(String::new(), |first| first.to_uppercase().chain(name).collect());
+        let mut text = format!("{}: {} -> {}", name, list(&self.inputs), list(&self.outputs));
+        if let Some(station) = &self.station {
+            text.push_str(&format!(" (at {})", station.name()));
+        }
+        text
+    }
+    /// Apply the recipe to an inventory. Either all inputs are consumed and all outputs
+    /// added, or the inventory is left untouched and `false` is returned.
+    pub fn apply(&self, inventory: &mut Inventory) -> bool {
+        let mut result = inventory.clone();
+        for (kind, n) in &self.inputs {
+            if !result.remove(kind, *n) {
+                return false;
+            }
+        }
+        for (kind, n) in &self.outputs {
+            for _ in 0 .. *n {
+                if !result.insert(Item::new(*kind)) {
+                    return false;
+                }
+            }
+        }
+        *inventory = result;
+        true
+    }
+}
+
+impl World {
+    pub(super) fn craft(&mut self, id: EntityId, recipe: &Recipe) -> Result<(), WorldError> {
+        if let Some(station) = &recipe.station {
+            let pos = *self.require::<Vec>(id)?;
+            let near_station = Dir::all().iter()
+                .any(|dir| self.tiles.get(pos + dir.to_vec()).terrain.as_ref() == Some(station));
+            if !near_station {
+                return Ok(());
+            }
+        }
+        if let Some(inventory) = self.get::<Inventory>(id) {
+            let mut inventory = inventory.clone();
+            if recipe.apply(&mut inventory) {
+                self.set(id, inventory);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Serialize, Deserialize};
+use std::vec;
+use super::*;
+use super::pathfinding::PathOptions;
+use crate::content::{content, CreatureDef};
+
+/// Ticks between two spawning rounds.
+const SPAWN_INTERVAL: u64 = 50;
+/// Creatures spawn this far from players, so they never pop up in plain view.
+const SPAWN_MIN_DIST: i32 = 12;
+const SPAWN_MAX_DIST: i32 = 24;
+/// Maximum number of creatures around a single player.
+const SPAWN_CAP: usize = 4;
+/// Creatures with no player this close are removed.
+const DESPAWN_DIST: i32 = 48;
+/// Search bound when a creature looks for a route to its target.
+const CHASE_SEARCH: usize = 256;
+
+/// A kind of creature, as defined in the content file.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct CreatureKind(pub(crate) u16);
+
+/// How a creature responds to a player it notices.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub enum Reaction {
+    Chase,
+    Flee,
+}
+
+/// What a creature is currently up to.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub enum Behaviour {
+    Wander,
+    Chase(EntityId),
+    Flee(EntityId),
+}
+
+impl CreatureKind {
+    /// Every kind of creature in the loaded content.
+    pub fn all() -> impl Iterator<Item=CreatureKind> {
+        (0 .. content().creatures.len() as u16).map(CreatureKind)
+    }
+    pub fn def(self) -> &'static CreatureDef {
+        &content().creatures[self.0 as usize]
+    }
+    pub fn max_hp(self) -> i64 {
+        self.def().hp
+    }
+    pub fn damage(self) -> i64 {
+        self.def().damage
+    }
+    /// How far away the creature notices players.
+    fn sight(self) -> i32 {
+        self.def().sight
+    }
+    /// Ticks between two actions.
+    fn think_delay(self) -> u64 {
+        self.def().think_delay
+    }
+    fn loot(self) -> Inventory {
+        let items = self.def().loot.iter()
+            .map(|(kind, count)| (Item::new(*kind), *count))
+            .collect::<vec::Vec<_>>();
+        Inventory { slots: items.len(), items, max_weight: None }
+    }
+    /// Which creature, if any, may spawn on the given tile, picked by the ground's spawn weights.
+    fn spawn_on(tile: &Tile, rng: &mut WorldRng) -> Option<CreatureKind> {
+        if !tile.is_free() {
+            return None;
+        }
+        let spawns = &tile.ground?.def().spawns;
+        let total = spawns.iter().map(|(_, weight)| weight).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.below(total);
+        for (kind, weight) in spawns {
+            if roll < *weight {
+                return Some(*kind);
+            }
+            roll -= weight;
+        }
+        None
+    }
+    pub fn create(self, pos: Vec) -> Entity {
+        Entity::new(pos, EntityKind::Creature(self))
+            .with(Health::full(self.max_hp()))
+            .with(Behaviour::Wander)
+    }
+    pub fn drop_loot(self, pos: Vec) -> WorldEvent {
+        self.loot().drop(pos)
+    }
+}
+
+impl World {
+    fn players(&self) -> impl Iterator<Item=(EntityId, &Vec)> {
+        self.query::<(&EntityKind, &Vec)>()
+            .filter(|(_, (kind, _))| matches!(kind, EntityKind::Player(_)))
+            .map(|(id, (_, pos))| (id, pos))
+    }
+    fn nearest_player(&self, pos: Vec, radius: i32) -> Option<(EntityId, Vec)> {
+        self.query_near::<(&EntityKind, &Vec)>(pos, radius)
+            .filter(|(_, (kind, _))| matches!(kind, EntityKind::Player(_)))
+            .min_by_key(|(id, (_, other))| ((**other - pos).len_sq(), *id))
+            .map(|(id, (_, other))| (id, *other))
+    }
+    /// Let a creature decide on and perform its next action.
+    pub(super) fn creature_think(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId) -> Result<(), WorldError> {
+        let kind = match self.require::<EntityKind>(id)? {
+            EntityKind::Creature(kind) => *kind,
+            _ => return Err(WorldError::InvalidState),
+        };
+        let pos = *self.require::<Vec>(id)?;
+        if self.nearest_player(pos, DESPAWN_DIST).is_none() {
+            evs.push(WorldEvent::DeleteEntity(id));
+            return Ok(());
+        }
+        let behaviour = match (kind.def().reaction, self.nearest_player(pos, kind.sight())) {
+            (Reaction::Chase, Some((target, _))) => Behaviour::Chase(target),
+            (Reaction::Flee, Some((threat, _))) => Behaviour::Flee(threat),
+            (_, None) => Behaviour::Wander,
+        };
+        self.set(id, behaviour);
+        match behaviour {
+            Behaviour::Chase(target) => {
+                let target_pos = *self.require::<Vec>(target)?;
+                if let Some(dir) = Dir::all().iter().copied().find(|dir| pos + dir.to_vec() == target_pos) {
+                    self.strike(evs, id, target, kind.damage(), dir)?;
+                } else {
+                    let opts = PathOptions { avoid_entities: true, max_nodes: CHASE_SEARCH };
+                    match self.find_path(pos, target_pos, opts).and_then(|path| path.first().copied()) {
+                        Some(dir) => { self.try_move(evs, id, dir)?; }
+                        None => self.step_towards(evs, id, pos, target_pos, true)?,
+                    }
+                }
+            }
+            Behaviour::Flee(threat) => {
+                let threat_pos = *self.require::<Vec>(threat)?;
+                self.step_towards(evs, id, pos, threat_pos, false)?;
+            }
+            Behaviour::Wander => {
+                if self.rng.chance(50) {
+                    let dir = Dir::all()[self.rng.below(4) as usize];
+                    self.try_move(evs, id, dir)?;
+                }
+            }
+        }
+        self.schedule(kind.think_delay(), WorldEvent::CreatureThink(id));
+        Ok(())
+    }
+    /// Take the single step that brings `id` closest to (or furthest from) `goal`.
+    fn step_towards(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, pos: Vec, goal: Vec, closer: bool) -> Result<(), WorldError> {
+        let best = Dir::all().iter()
+            .copied()
+            .filter(|dir| self.can_enter(pos, pos + dir.to_vec()))
+            .min_by_key(|dir| {
+                let dist = (goal - (pos + dir.to_vec())).len_sq();
+                if closer { dist } else { -dist }
+            });
+        if let Some(dir) = best {
+            self.try_move(evs, id, dir)?;
+        }
+        Ok(())
+    }
+    /// Periodically populate the area around each player with creatures.
+    pub(super) fn spawn_creatures(&mut self, evs: &mut vec::Vec<WorldEvent>) {
+        let players = self.players().map(|(_, pos)| *pos).collect::<vec::Vec<_>>();
+        for player_pos in players {
+            let nearby = self.query_near::<&EntityKind>(player_pos, SPAWN_MAX_DIST)
+                .filter(|(_, kind)| matches!(kind, EntityKind::Creature(_)))
+                .count();
+            if nearby >= SPAWN_CAP {
+                continue;
+            }
+            let offset = Vec::new(
+                self.rng.range(-SPAWN_MAX_DIST, SPAWN_MAX_DIST + 1),
+                self.rng.range(-SPAWN_MAX_DIST, SPAWN_MAX_DIST + 1));
+            if offset.len_sq() < SPAWN_MIN_DIST * SPAWN_MIN_DIST {
+                continue;
+            }
+            let pos = player_pos + offset;
+            if !self.is_free(pos) {
+                continue;
+            }
+            if let Some(kind) = CreatureKind::spawn_on(&self.tiles.get(pos), &mut self.rng) {
+                evs.push(WorldEvent::CreateEntity(kind.create(pos)));
+            }
+        }
+        self.schedule(SPAWN_INTERVAL, WorldEvent::SpawnCreatures);
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub enum EquipSlot {
+    Hand,
+    Body,
+}
+
+/// Items an entity is wielding or wearing, outside its inventory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Equipment {
+    pub hand: Option<Item>,
+    pub body: Option<Item>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipSlot) -> Option<&Item> {
+        match slot {
+            EquipSlot::Hand => self.hand.as_ref(),
+            EquipSlot::Body => self.body.as_ref(),
+        }
+    }
+    pub fn items(&self) -> impl Iterator<Item=&Item> {
+        self.hand.iter().chain(self.body.iter())
+    }
+    fn slot_mut(&mut self, slot: EquipSlot) -> &mut Option<Item> {
+        match slot {
+            EquipSlot::Hand => &mut self.hand,
+            EquipSlot::Body => &mut self.body,
+        }
+    }
+    /// Damage dealt when attacking with whatever is in hand.
+    pub fn damage(&self) -> i64 {
+        self.hand.as_ref().map_or(1, |item| item.kind.damage())
+    }
+    /// Damage taken away from every hit by whatever is worn.
+    pub fn defense(&self) -> i64 {
+        self.body.as_ref().map_or(0, |item| item.kind.defense())
+    }
+    /// How much a hit with whatever is in hand wears down the given terrain.
+    pub fn break_power(&self, terrain: &TerrainKind) -> u32 {
+        match &self.hand {
+            Some(item) => item.kind.break_power(terrain),
+            None => terrain.bare_hands_power(),
+        }
+    }
+}
+
+impl ItemKind {
+    pub fn slot(&self) -> Option<EquipSlot> {
+        self.def().slot
+    }
+    /// Number of uses before the item breaks, for tools, weapons and armour.
+    pub fn max_durability(&self) -> Option<u32> {
+        self.def().durability
+    }
+    pub fn defense(&self) -> i64 {
+        self.def().defense
+    }
+    pub fn damage(&self) -> i64 {
+        self.def().damage
+    }
+    pub fn break_power(&self, terrain: &TerrainKind) -> u32 {
+        self.def().power.iter()
+            .find(|(kind, _)| kind == terrain)
+            .map_or_else(|| terrain.bare_hands_power(), |(_, power)| *power)
+    }
+}
+
+impl TerrainKind {
+    /// Some terrain, like rock, cannot be broken without a tool.
+    fn bare_hands_power(&self) -> u32 {
+        if self.def().needs_tool { 0 } else { 1 }
+    }
+}
+
+impl World {
+    /// Move one item from inventory slot `ix` into its equipment slot, putting back what was there.
+    pub(super) fn equip(&mut self, id: EntityId, ix: usize) -> Result<(), WorldError> {
+        let (mut inventory, mut equipment) = match (self.get::<Inventory>(id), self.get::<Equipment>(id)) {
+            (Some(inv), Some(eq)) => (inv.clone(), eq.clone()),
+            _ => return Ok(()),
+        };
+        let slot = match inventory.get(ix).and_then(|item| item.kind.slot()) {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        let item = inventory.take_one(ix).ok_or(WorldError::OutOfBounds)?;
+        if let Some(old) = equipment.slot_mut(slot).replace(item) {
+            if !inventory.insert(old) {
+                return Ok(());
+            }
+        }
+        self.set(id, inventory);
+        self.set(id, equipment);
+        Ok(())
+    }
+    pub(super) fn unequip(&mut self, id: EntityId, slot: EquipSlot) {
+        let (mut inventory, mut equipment) = match (self.get::<Inventory>(id), self.get::<Equipment>(id)) {
+            (Some(inv), Some(eq)) => (inv.clone(), eq.clone()),
+            _ => return,
+        };
+        match equipment.slot_mut(slot).take() {
+            Some(item) => if !inventory.insert(item) {
+                return;
+            },
+            None => return,
+        }
+        self.set(id, inventory);
+        self.set(id, equipment);
+    }
+    /// Use up one point of durability of the item in the given slot, destroying it at zero.
+    pub(super) fn wear(&mut self, id: EntityId, slot: EquipSlot) {
+        self.modify(id, |equipment: &mut Equipment| {
+            let slot = equipment.slot_mut(slot);
+            if let Some(Item { durability: Some(left), .. }) = slot {
+                *left = left.saturating_sub(1);
+                if *left == 0 {
+                    *slot = None;
+                }
+            }
+        });
+    }
+    pub(super) fn equipment_of(&self, id: EntityId) -> Equipment {
+        self.get::<Equipment>(id).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use super::*;
+use super::fov::VIEW_RADIUS;
+
+/// The tiles a player has seen, as one bit per tile in chunk-sized blocks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExploredMap {
+    chunks: Map<(i32, i32, i32), [u32; CHUNK_SIZE], ArcK>,
+}
+
+impl Default for ExploredMap {
+    fn default() -> ExploredMap {
+        ExploredMap { chunks: Map::new_with_ptr_kind() }
+    }
+}
+
+impl ExploredMap {
+    fn conv_pos(pos: Vec) -> ((i32, i32, i32), usize, u32) {
+        let chunk = (pos.z, pos.x.div_euclid(CHUNK_SIZE as i32), pos.y.div_euclid(CHUNK_SIZE as i32));
+        let row = pos.y.rem_euclid(CHUNK_SIZE as i32) as usize;
+        let bit = 1 << pos.x.rem_euclid(CHUNK_SIZE as i32);
+        (chunk, row, bit)
+    }
+    pub fn contains(&self, pos: Vec) -> bool {
+        let (chunk, row, bit) = ExploredMap::conv_pos(pos);
+        self.chunks.get(&chunk).is_some_and(|rows| rows[row] & bit != 0)
+    }
+    /// Mark all of `seen` as explored, touching each chunk only once.
+    fn insert_all(&mut self, seen: impl Iterator<Item=Vec>) {
+        let mut changed = HashMap::new();
+        for pos in seen {
+            let (chunk, row, bit) = ExploredMap::conv_pos(pos);
+            let rows = changed.entry(chunk)
+                .or_insert_with(|| self.chunks.get(&chunk).copied().unwrap_or([0; CHUNK_SIZE]));
+            rows[row] |= bit;
+        }
+        for (chunk, rows) in changed {
+            if self.chunks.get(&chunk) != Some(&rows) {
+                self.chunks.insert_mut(chunk, rows);
+            }
+        }
+    }
+}
+
+impl World {
+    /// Tiles the client's players have seen so far, if any.
+    pub fn explored(&self, client: ClientId) -> Option<&ExploredMap> {
+        self.explored.get(&client)
+    }
+    /// Remember every lit tile a player can currently see. This runs on every step, so the view
+    /// is only searched as far as there can be lit tiles.
+    pub(super) fn explore(&mut self, id: EntityId) {
+        let (pos, client) = match (self.get::<Vec>(id), self.get::<EntityKind>(id)) {
+            (Some(pos), Some(EntityKind::Player(client))) => (*pos, *client),
+            _ => return,
+        };
+        let mut explored = self.explored.get(&client).cloned().unwrap_or_default();
+        if self.ambient_light(pos.z) > 0 {
+            // Everything in view is lit, so there is no need for a light map.
+            explored.insert_all(self.field_of_view(pos, VIEW_RADIUS).iter());
+        } else {
+            let span = Vec::new(VIEW_RADIUS, VIEW_RADIUS);
+            let reach = self.light_sources(pos - span, pos + span).into_iter()
+                .map(|(source, radius)| ((source - pos).len_sq() as f64).sqrt().ceil() as i32 + radius)
+                .max();
+            // Without any light nearby there is nothing to see.
+            if let Some(radius) = reach.map(|reach| reach.min(VIEW_RADIUS)) {
+                let span = Vec::new(radius, radius);
+                let lights = self.light_map(pos - span, pos + span);
+                explored.insert_all(self.field_of_view(pos, radius).iter().filter(|seen| lights.get(*seen) > 0));
+            }
+        }
+        self.explored.insert_mut(client, explored);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use super::*;
+
+/// How far a player can see, in tiles; enough to cover the screen.
+pub const VIEW_RADIUS: i32 = 40;
+
+/// Octant transforms for shadowcasting, as (xx, xy, yx, yy).
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1), (0, 1, 1, 0), (0, -1, 1, 0), (-1, 0, 0, 1),
+    (-1, 0, 0, -1), (0, -1, -1, 0), (0, 1, -1, 0), (1, 0, 0, -1),
+];
+
+impl TerrainKind {
+    /// Whether the terrain hides what is behind it.
+    pub fn blocks_sight(&self) -> bool {
+        self.def().opaque
+    }
+}
+
+/// The tiles visible from some position.
+pub struct Fov {
+    visible: HashSet<Vec>,
+}
+
+impl Fov {
+    pub fn contains(&self, pos: Vec) -> bool {
+        self.visible.contains(&pos)
+    }
+    pub fn iter(&self) -> impl Iterator<Item=Vec> + '_ {
+        self.visible.iter().copied()
+    }
+}
+
+impl World {
+    fn is_opaque(&self, pos: Vec) -> bool {
+        self.tiles.get(pos).terrain.as_ref().is_some_and(TerrainKind::blocks_sight)
+    }
+    /// Tiles on the layer of `origin` visible from it within `radius`, by recursive
+    /// shadowcasting. Opaque tiles are themselves visible, but hide what is behind them.
+    pub fn field_of_view(&self, origin: Vec, radius: i32) -> Fov {
+        let mut visible = HashSet::new();
+        visible.insert(origin);
+        for &octant in OCTANTS.iter() {
+            self.cast_light(&mut visible, origin, radius, 1, 1.0, 0.0, octant);
+        }
+        Fov { visible }
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn cast_light(
+        &self,
+        visible: &mut HashSet<Vec>,
+        origin: Vec,
+        radius: i32,
+        row: i32,
+        mut start: f64,
+        end: f64,
+        (xx, xy, yx, yy): (i32, i32, i32, i32),
+    ) {
+        if start < end {
+            return;
+        }
+        let mut new_start = 0.0;
+        for distance in row ..= radius {
+            let dy = -distance;
+            let mut blocked = false;
+            for dx in -distance ..= 0 {
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+                let pos = origin + Vec::new(dx * xx + dy * xy, dx * yx + dy * yy);
+                if dx * dx + dy * dy <= radius * radius {
+                    visible.insert(pos);
+                }
+                let opaque = self.is_opaque(pos);
+                if blocked {
+                    if opaque {
+                        new_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if opaque && distance < radius {
+                    blocked = true;
+                    self.cast_light(visible, origin, radius, distance + 1, start, left_slope, (xx, xy, yx, yy));
+                    new_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::*;
+use super::spatial::SpatialIndex;
+use super::layers::{SURFACE, UNDERGROUND, LAYERS};
+
+/// Chunks are generated this many chunks around a player, enough to cover their view.
+const GENERATE_RADIUS: i32 = 2;
+/// Elevation, out of 1024, below which there are lakes and above which there are mountains.
+const WATER_LEVEL: i64 = 300;
+const MOUNTAIN_LEVEL: i64 = 700;
+
+/// A hash of a position, uniform over `u64`.
+fn hash(seed: u64, x: i32, y: i32) -> u64 {
+    let mut z = seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Smoothly interpolated value noise in `0 .. 1024`, with features about `scale` tiles apart.
+/// Integer arithmetic only, so every client generates the same terrain.
+fn value_noise(seed: u64, pos: Vec, scale: i32) -> i64 {
+    let (gx, fx) = (pos.x.div_euclid(scale), pos.x.rem_euclid(scale) as i64);
+    let (gy, fy) = (pos.y.div_euclid(scale), pos.y.rem_euclid(scale) as i64);
+    let s = scale as i64;
+    let smooth = |f: i64| f * f * (3 * s - 2 * f) / (s * s);
+    let (wx, wy) = (smooth(fx), smooth(fy));
+    let corner = |dx: i32, dy: i32| (hash(seed, gx + dx, gy + dy) % 1024) as i64;
+    let top = corner(0, 0) * (s - wx) + corner(1, 0) * wx;
+    let bottom = corner(0, 1) * (s - wx) + corner(1, 1) * wx;
+    (top * (s - wy) + bottom * wy) / (s * s)
+}
+
+/// Pure description of the generated landscape for a given seed.
+struct Generator {
+    seed: u64,
+}
+
+impl Generator {
+    fn elevation(&self, pos: Vec) -> i64 {
+        (value_noise(self.seed, pos, 64) * 2 + value_noise(self.seed.wrapping_add(1), pos, 16)) / 3
+    }
+    fn is_mountain(&self, pos: Vec) -> bool {
+        self.elevation(pos) > MOUNTAIN_LEVEL
+    }
+    fn is_mountain_edge(&self, pos: Vec) -> bool {
+        self.is_mountain(pos) && Dir::all().iter().any(|dir| !self.is_mountain(pos + dir.to_vec()))
+    }
+    fn is_entrance(&self, pos: Vec) -> bool {
+        hash(self.seed.wrapping_add(3), pos.x, pos.y).is_multiple_of(24) && self.is_mountain_edge(pos)
+    }
+    /// Open space under a mountain. Entrances always lead into a small cave.
+    fn is_cave(&self, pos: Vec) -> bool {
+        value_noise(self.seed.wrapping_add(4), pos, 12) > 560 ||
+            (-2 ..= 2).any(|dx| (-2 ..= 2).any(|dy| self.is_entrance(pos + Vec::new(dx, dy))))
+    }
+    fn tile(&self, pos: Vec) -> Tile {
+        let palette = &content().palette;
+        let rock = |terrain| Tile { ground: Some(palette.rock), terrain: Some(terrain) };
+        match pos.z {
+            SURFACE => {}
+            UNDERGROUND if self.is_mountain(pos) => return if self.is_entrance(pos) {
+                rock(palette.entrance)
+            } else if self.is_mountain_edge(pos) || !self.is_cave(pos) {
+                rock(palette.cliff)
+            } else {
+                Tile { ground: Some(palette.rock), terrain: None }
+            },
+            _ => return rock(palette.cliff),
+        }
+        if self.is_mountain(pos) {
+            return rock(if self.is_entrance(pos) { palette.entrance } else { palette.cliff });
+        }
+        if self.elevation(pos) < WATER_LEVEL {
+            return Tile { ground: Some(palette.water), terrain: None };
+        }
+        let forest = value_noise(self.seed.wrapping_add(2), pos, 40) > 600;
+        let roll = hash(self.seed.wrapping_add(5), pos.x, pos.y) % 50;
+        let tree = if forest { !roll.is_multiple_of(4) } else { roll == 0 };
+        Tile {
+            ground: Some(palette.grass),
+            terrain: if tree { Some(palette.tree) } else { None },
+        }
+    }
+    fn chunk(&self, z: i32, cx: i32, cy: i32) -> Chunk {
+        let mut chunk: Chunk = Default::default();
+        for (px, column) in chunk.iter_mut().enumerate() {
+            for (py, tile) in column.iter_mut().enumerate() {
+                *tile = self.tile(Vec::new_in_layer(cx * CHUNK_SIZE as i32 + px as i32, cy * CHUNK_SIZE as i32 + py as i32, z));
+            }
+        }
+        chunk
+    }
+}
+
+/// The starting map: generated terrain with the hand-made level stamped over it at the origin.
+pub(super) fn starting_tiles(seed: u64) -> TileMap {
+    let level = level_loader::load_level();
+    let generator = Generator { seed };
+    let mut tiles = TileMap::new();
+    for (z, cx, cy) in level.chunk_coords() {
+        tiles.set_chunk(z, cx, cy, generator.chunk(z, cx, cy));
+    }
+    tiles.stamp(&level);
+    tiles
+}
+
+impl World {
+    /// Generate any missing chunks around `pos`, on every layer so entrances always lead somewhere.
+    pub(super) fn generate_near(&mut self, pos: Vec) {
+        let generator = Generator { seed: self.seed };
+        let (_, cx, cy) = SpatialIndex::chunk_of(pos);
+        for &z in LAYERS.iter() {
+            for x in cx - GENERATE_RADIUS ..= cx + GENERATE_RADIUS {
+                for y in cy - GENERATE_RADIUS ..= cy + GENERATE_RADIUS {
+                    if !self.tiles.has_chunk(z, x, y) {
+                        self.tiles.set_chunk(z, x, y, generator.chunk(z, x, y));
+                    }
+                }
+            }
+        }
+    }
+    /// Generate the chunk holding `pos`, if it is missing.
+    pub(super) fn generate_chunk_at(&mut self, pos: Vec) {
+        let (z, cx, cy) = SpatialIndex::chunk_of(pos);
+        if !self.tiles.has_chunk(z, cx, cy) {
+            self.tiles.set_chunk(z, cx, cy, Generator { seed: self.seed }.chunk(z, cx, cy));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::vec;
+use super::*;
+
+impl Inventory {
+    /// Take up to `count` items out of stack `ix`.
+    pub(super) fn take(&mut self, ix: usize, count: usize) -> Option<(Item, usize)> {
+        let (item, size) = self.items.get_mut(ix)?;
+        let count = count.min(*size);
+        let item = item.clone();
+        *size -= count;
+        if *size == 0 {
+            self.items.remove(ix);
+        }
+        Some((item, count))
+    }
+    /// Insert a whole stack, or nothing if it does not fit.
+    pub fn insert_stack(&mut self, item: &Item, count: usize) -> bool {
+        let mut result = self.clone();
+        for _ in 0 .. count {
+            if !result.insert(item.clone()) {
+                return false;
+            }
+        }
+        *self = result;
+        true
+    }
+}
+
+impl World {
+    /// Put up to `count` items from stack `ix` on the ground under the entity.
+    pub(super) fn drop_items(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, ix: usize, count: usize) -> Result<(), WorldError> {
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        if let Some((item, count)) = inventory.take(ix, count) {
+            let pos = *self.require::<Vec>(id)?;
+            evs.push(Inventory::of_stack(item, count).drop(pos));
+            self.set(id, inventory);
+        }
+        Ok(())
+    }
+    /// Hand up to `count` items from stack `ix` to the player standing next to the entity in `dir`.
+    /// Nothing is given unless the receiver can take all of it.
+    pub(super) fn give_items(&mut self, id: EntityId, ix: usize, count: usize, dir: Dir) -> Result<(), WorldError> {
+        let pos = *self.require::<Vec>(id)? + dir.to_vec();
+        let receiver = self.query_at::<(&EntityKind, &Inventory)>(pos)
+            .find(|(_, (kind, _))| matches!(kind, EntityKind::Player(_)))
+            .map(|(rid, _)| rid);
+        let receiver = match receiver {
+            Some(rid) if rid != id => rid,
+            _ => return Ok(()),
+        };
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        let mut other = self.require::<Inventory>(receiver)?.clone();
+        if let Some((item, count)) = inventory.take(ix, count) {
+            if other.insert_stack(&item, count) {
+                self.set(id, inventory);
+                self.set(receiver, other);
+            }
+        }
+        Ok(())
+    }
+    /// Move `count` items from stack `ix` into a stack of their own, if there is a free slot.
+    /// Splitting off the whole stack or more is an error.
+    pub(super) fn split_stack(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, ix: usize, count: usize) -> Result<(), WorldError> {
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        let full = inventory.items.len() >= inventory.slots;
+        match inventory.items.get_mut(ix) {
+            Some(_) if full =>
+                evs.push(WorldEvent::Notify(id, "There is no free slot to split the stack into.".to_string())),
+            Some((item, size)) if *size > count => {
+                *size -= count;
+                let item = item.clone();
+                inventory.items.push((item, count));
+                self.set(id, inventory);
+            }
+            _ => return Err(WorldError::InvalidState),
+        }
+        Ok(())
+    }
+    /// Move items from stack `from` into stack `into` if they hold the same stackable item,
+    /// up to the maximum stack size. Whatever does not fit stays where it was. It is an error
+    /// if nothing can be moved.
+    pub(super) fn merge_stacks(&mut self, id: EntityId, from: usize, into: usize) -> Result<(), WorldError> {
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        if from == into || from >= inventory.items.len() || into >= inventory.items.len() {
+            return Err(WorldError::InvalidState);
+        }
+        if !inventory.items[from].0.stacks_with(&inventory.items[into].0) {
+            return Err(WorldError::InvalidState);
+        }
+        let room = inventory.items[into].0.kind.max_stack().saturating_sub(inventory.items[into].1);
+        let moved = room.min(inventory.items[from].1);
+        if moved == 0 {
+            return Err(WorldError::InvalidState);
+        }
+        inventory.items[into].1 += moved;
+        inventory.items[from].1 -= moved;
+        if inventory.items[from].1 == 0 {
+            inventory.items.remove(from);
+        }
+        self.set(id, inventory);
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use super::*;
+
+/// The layer players start on, lit by the sun.
+pub const SURFACE: i32 = 0;
+/// The caves below the mountains.
+pub const UNDERGROUND: i32 = -1;
+/// The layers that are generated, from the top down.
+pub const LAYERS: [i32; 2] = [SURFACE, UNDERGROUND];
+
+impl World {
+    /// The entrance on a neighbouring layer that the entrance at `pos` leads to, if any.
+    pub fn linked_entrance(&self, pos: Vec) -> Option<Vec> {
+        let is_entrance = |pos| self.tiles.get(pos).terrain.as_ref().is_some_and(TerrainKind::is_entrance);
+        if !is_entrance(pos) {
+            return None;
+        }
+        [pos.z - 1, pos.z + 1].iter()
+            .map(|&z| Vec::new_in_layer(pos.x, pos.y, z))
+            .find(|other| is_entrance(*other))
+    }
+}
@@ -0,0 +1,148 @@
+use std::vec;
+use super::*;
+use super::layers::SURFACE;
+
+/// Length of a full day and night, in ticks.
+pub const DAY_LENGTH: u64 = 3000;
+/// Light level of a tile in full daylight.
+pub const MAX_LIGHT: u8 = 5;
+/// Outdoor light level at night.
+const MOONLIGHT: u8 = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl DayPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            DayPhase::Dawn => "dawn",
+            DayPhase::Day => "day",
+            DayPhase::Dusk => "dusk",
+            DayPhase::Night => "night",
+        }
+    }
+}
+
+impl TerrainKind {
+    /// Radius lit by the terrain, if it gives off light.
+    pub fn light_radius(&self) -> Option<i32> {
+        self.def().light
+    }
+}
+
+impl ItemKind {
+    /// Radius lit around an entity holding the item, if it gives off light.
+    pub fn light_radius(&self) -> Option<i32> {
+        self.def().light
+    }
+}
+
+/// Largest radius of any light source in the content definitions.
+fn max_light_radius() -> i32 {
+    let content = content();
+    content.terrain.iter().map(|def| def.light)
+        .chain(content.items.iter().map(|def| def.light))
+        .flatten()
+        .max()
+        .unwrap_or(0)
+}
+
+/// Light levels for a rectangle of tiles.
+pub struct LightMap {
+    min: Vec,
+    width: i32,
+    levels: vec::Vec<u8>,
+}
+
+impl LightMap {
+    /// Light level at `pos`, which must lie inside the rectangle the map was made for.
+    pub fn get(&self, pos: Vec) -> u8 {
+        let rel = pos - self.min;
+        self.levels[(rel.y * self.width + rel.x) as usize]
+    }
+}
+
+impl World {
+    /// Position within the current day, in ticks.
+    fn time_of_day(&self) -> u64 {
+        self.time % DAY_LENGTH
+    }
+    /// The day starts at dawn; dawn and dusk each take an eighth of it, the day and night the rest.
+    pub fn day_phase(&self) -> DayPhase {
+        match self.time_of_day() * 8 / DAY_LENGTH {
+            0 => DayPhase::Dawn,
+            1 ..= 3 => DayPhase::Day,
+            4 => DayPhase::Dusk,
+            _ => DayPhase::Night,
+        }
+    }
+    /// Light level outdoors, fading in at dawn and out at dusk.
+    pub fn daylight(&self) -> u8 {
+        let phase_length = DAY_LENGTH / 8;
+        let fade = |t: u64| MOONLIGHT + ((MAX_LIGHT - MOONLIGHT) as u64 * t / phase_length) as u8;
+        match self.day_phase() {
+            DayPhase::Dawn => fade(self.time_of_day()),
+            DayPhase::Day => MAX_LIGHT,
+            DayPhase::Dusk => fade(5 * phase_length - self.time_of_day()),
+            DayPhase::Night => MOONLIGHT,
+        }
+    }
+    /// Light level everywhere in layer `z` before any light sources are counted. The surface
+    /// is lit by the sun, or at least the moon; underground only light sources give light.
+    pub(super) fn ambient_light(&self, z: i32) -> u8 {
+        if z == SURFACE { self.daylight() } else { 0 }
+    }
+    /// Light sources that may reach into the rectangle spanned by `min` and `max`, with their radius.
+    pub(super) fn light_sources(&self, min: Vec, max: Vec) -> vec::Vec<(Vec, i32)> {
+        let max_radius = max_light_radius();
+        let reach = Vec::new(max_radius, max_radius);
+        let (min, max) = (min - reach, max + reach);
+        let mut sources = vec::Vec::new();
+        for y in min.y ..= max.y {
+            for x in min.x ..= max.x {
+                let pos = Vec::new_in_layer(x, y, min.z);
+                if let Some(radius) = self.tiles.get(pos).terrain.as_ref().and_then(|terrain| terrain.light_radius()) {
+                    sources.push((pos, radius));
+                }
+            }
+        }
+        for (_, (pos, equipment)) in self.query_in::<(&Vec, &Equipment)>(min, max) {
+            if let Some(radius) = equipment.hand.as_ref().and_then(|item| item.kind.light_radius()) {
+                sources.push((*pos, radius));
+            }
+        }
+        sources
+    }
+    /// Light levels of the tiles in the rectangle spanned by `min` and `max`, which must lie in one layer.
+    pub fn light_map(&self, min: Vec, max: Vec) -> LightMap {
+        let width = max.x - min.x + 1;
+        let height = max.y - min.y + 1;
+        let levels = vec![self.ambient_light(min.z); (width * height) as usize];
+        let mut map = LightMap { min, width, levels };
+        for (source, radius) in self.light_sources(min, max) {
+            let r_sq = radius * radius;
+            for y in (source.y - radius).max(min.y) ..= (source.y + radius).min(max.y) {
+                for x in (source.x - radius).max(min.x) ..= (source.x + radius).min(max.x) {
+                    let pos = Vec::new_in_layer(x, y, min.z);
+                    let d_sq = (pos - source).len_sq();
+                    if d_sq > r_sq {
+                        continue;
+                    }
+                    let level = (MAX_LIGHT as u32 * (r_sq - d_sq) as u32).div_ceil(r_sq as u32) as u8;
+                    let rel = pos - min;
+                    let cell = &mut map.levels[(rel.y * width + rel.x) as usize];
+                    *cell = (*cell).max(level);
+                }
+            }
+        }
+        map
+    }
+    pub fn light_at(&self, pos: Vec) -> u8 {
+        self.light_map(pos, pos).get(pos)
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::{BinaryHeap, HashMap};
+use std::cmp::Reverse;
+use std::vec;
+use super::*;
+
+/// Limits and options for a path search.
+#[derive(Debug, Copy, Clone)]
+pub struct PathOptions {
+    /// Treat tiles occupied by collidable entities as blocked. The goal tile is always allowed,
+    /// so entities can path towards each other.
+    pub avoid_entities: bool,
+    /// Give up after expanding this many tiles.
+    pub max_nodes: usize,
+}
+
+impl Default for PathOptions {
+    fn default() -> PathOptions {
+        PathOptions {
+            avoid_entities: true,
+            max_nodes: 1024,
+        }
+    }
+}
+
+impl World {
+    /// Find the shortest sequence of steps from `from` to `to` with A*, using the same movement
+    /// rules as `PlayerActionEvent::Move`, so paths may lead through entrances to other layers.
+    /// Returns `None` if there is no path within the search bound. Stepping onto an entrance leads
+    /// away from it, so if `to` is one, the path may end on the entrance it is linked to instead.
+    pub fn find_path(&self, from: Vec, to: Vec, opts: PathOptions) -> Option<vec::Vec<Dir>> {
+        let linked_goal = self.linked_entrance(to);
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Vec, (Vec, Dir)> = HashMap::new();
+        let mut cost: HashMap<Vec, u32> = HashMap::new();
+        let mut expanded = 0;
+        cost.insert(from, 0);
+        open.push(Reverse((heuristic(from, to), 0, from)));
+        while let Some(Reverse((_, g, pos))) = open.pop() {
+            if pos == to || Some(pos) == linked_goal {
+                return Some(reconstruct(&came_from, from, pos));
+            }
+            if g > cost[&pos] {
+                continue; // stale entry, a cheaper route was found later
+            }
+            expanded += 1;
+            if expanded > opts.max_nodes {
+                return None;
+            }
+            for &dir in Dir::all().iter() {
+                let step = pos + dir.to_vec();
+                if !self.tile_step(pos, step) {
+                    continue;
+                }
+                if opts.avoid_entities && step != to && self.has_collider_at(step) {
+                    continue;
+                }
+                // Stepping onto an entrance leads through to the other layer, as in `try_move`.
+                let next = match self.linked_entrance(step) {
+                    Some(other) if !(opts.avoid_entities && other != to && self.has_collider_at(other)) => other,
+                    _ => step,
+                };
+                let next_g = g + 1;
+                if cost.get(&next).is_none_or(|&old| next_g < old) {
+                    cost.insert(next, next_g);
+                    came_from.insert(next, (pos, dir));
+                    open.push(Reverse((next_g + heuristic(next, to), next_g, next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A lower bound on the steps from `a` to `b`. Every step changes the layer by at most one.
+fn heuristic(a: Vec, b: Vec) -> u32 {
+    let d = b - a;
+    d.manhattan().max(d.z.abs()) as u32
+}
+
+fn reconstruct(came_from: &HashMap<Vec, (Vec, Dir)>, from: Vec, to: Vec) -> vec::Vec<Dir> {
+    let mut path = vec::Vec::new();
+    let mut pos = to;
+    while pos != from {
+        let (prev, dir) = came_from[&pos];
+        path.push(dir);
+        pos = prev;
+    }
+    path.reverse();
+    path
+}
@@ -0,0 +1,40 @@
+use std::vec;
+use super::*;
+
+/// Ticks a dead player waits before coming back.
+pub const RESPAWN_DELAY: u64 = 50;
+
+impl World {
+    /// A player died: everything they carried is left where they fell, and they come back later.
+    pub(super) fn kill_player(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, client: ClientId) -> Result<(), WorldError> {
+        // Offered items go back into the inventory first, so they are dropped with the rest.
+        self.cancel_trade(evs, id);
+        let mut remains = Inventory::new(usize::MAX, None);
+        if let Some(inventory) = self.get::<Inventory>(id) {
+            remains.insert_inventory(&mut inventory.clone());
+        }
+        if let Some(equipment) = self.get::<Equipment>(id) {
+            for item in equipment.items() {
+                remains.insert(item.clone());
+            }
+        }
+        if !remains.is_empty() {
+            evs.push(remains.drop(*self.require::<Vec>(id)?));
+        }
+        evs.push(WorldEvent::DeleteEntity(id));
+        let at = self.time + RESPAWN_DELAY;
+        self.dead_players.insert_mut(client, at);
+        self.timers.schedule(at, WorldEvent::Respawn(client));
+        Ok(())
+    }
+    /// Bring a dead player back, unless they left in the meantime.
+    pub(super) fn respawn(&mut self, evs: &mut vec::Vec<WorldEvent>, client: ClientId) {
+        if self.dead_players.remove_mut(&client) {
+            evs.push(self.create_player_spawn_event(client));
+        }
+    }
+    /// World time at which a dead player respawns, or `None` if they are not dead.
+    pub fn respawn_time(&self, client: ClientId) -> Option<u64> {
+        self.dead_players.get(&client).copied()
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Serialize, Deserialize};
+
+/// Deterministic random number generator that lives inside the world state,
+/// so every client that replays the same events draws the same numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldRng(u64);
+
+impl WorldRng {
+    pub fn new(seed: u64) -> WorldRng {
+        WorldRng(seed)
+    }
+    /// splitmix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    /// Uniform number in `0 .. n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+    pub fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        lo + self.below((hi - lo) as u64) as i32
+    }
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
@@ -0,0 +1,66 @@
+use rpds::RedBlackTreeMap as Map;
+use rpds::RedBlackTreeSet as Set;
+use archery::shared_pointer::kind::ArcK;
+use crate::geom::Vec;
+use super::{EntityId, CHUNK_SIZE};
+
+/// Index from chunks to the entities standing inside them.
+///
+/// The index only knows which chunk an entity is in; callers filter on the
+/// exact position themselves, using the stored positions as the source of truth.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    /// Keyed by layer and chunk coordinates.
+    chunks: Map<(i32, i32, i32), Set<EntityId, ArcK>, ArcK>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> SpatialIndex {
+        SpatialIndex {
+            chunks: Map::new_with_ptr_kind(),
+        }
+    }
+}
+
+impl SpatialIndex {
+    pub fn chunk_of(pos: Vec) -> (i32, i32, i32) {
+        (pos.z, pos.x.div_euclid(CHUNK_SIZE as i32), pos.y.div_euclid(CHUNK_SIZE as i32))
+    }
+    pub fn insert(&mut self, id: EntityId, pos: Vec) {
+        let key = SpatialIndex::chunk_of(pos);
+        let mut set = match self.chunks.get(&key) {
+            Some(set) => set.clone(),
+            None => Set::new_with_ptr_kind(),
+        };
+        set.insert_mut(id);
+        self.chunks.insert_mut(key, set);
+    }
+    pub fn remove(&mut self, id: EntityId, pos: Vec) {
+        let key = SpatialIndex::chunk_of(pos);
+        if let Some(set) = self.chunks.get(&key) {
+            let mut set = set.clone();
+            set.remove_mut(&id);
+            if set.is_empty() {
+                self.chunks.remove_mut(&key);
+            } else {
+                self.chunks.insert_mut(key, set);
+            }
+        }
+    }
+    pub fn relocate(&mut self, id: EntityId, from: Vec, to: Vec) {
+        if SpatialIndex::chunk_of(from) != SpatialIndex::chunk_of(to) {
+            self.remove(id, from);
+            self.insert(id, to);
+        }
+    }
+    /// All entities in chunks overlapping the box spanned by `min` and `max` (inclusive).
+    pub fn candidates(&self, min: Vec, max: Vec) -> impl Iterator<Item=EntityId> + '_ {
+        let (z0, cx0, cy0) = SpatialIndex::chunk_of(min);
+        let (z1, cx1, cy1) = SpatialIndex::chunk_of(max);
+        (z0 ..= z1)
+            .flat_map(move |z| (cx0 ..= cx1).map(move |cx| (z, cx)))
+            .flat_map(move |(z, cx)| (cy0 ..= cy1).map(move |cy| (z, cx, cy)))
+            .filter_map(move |key| self.chunks.get(&key))
+            .flat_map(|set| set.iter().copied())
+    }
+}
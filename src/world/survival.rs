@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+use std::vec;
+use super::*;
+
+/// Ticks between two updates of an entity's survival stats.
+pub const SURVIVAL_INTERVAL: u64 = 20;
+pub const MAX_FOOD: i64 = 100;
+pub const MAX_STAMINA: i64 = 20;
+/// Stamina gained per update while not starving.
+const STAMINA_REGEN: i64 = 2;
+/// Food needed before lost health starts to come back.
+const REGEN_FOOD: i64 = 50;
+/// Stamina needed to swing a weapon or tool.
+pub const ATTACK_STAMINA: i64 = 2;
+
+/// Needs that change over world time, for entities that have to look after themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Survival {
+    pub food: i64,
+    pub stamina: i64,
+}
+
+impl Default for Survival {
+    fn default() -> Survival {
+        Survival {
+            food: MAX_FOOD,
+            stamina: MAX_STAMINA,
+        }
+    }
+}
+
+impl ItemKind {
+    /// Food restored by eating the item, if it is edible.
+    pub fn food_value(&self) -> Option<i64> {
+        self.def().food
+    }
+}
+
+impl World {
+    /// Periodic update: hunger grows, stamina comes back, and a well fed and rested entity heals.
+    /// Starving entities lose health instead.
+    pub(super) fn survival_tick(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId) -> Result<(), WorldError> {
+        let mut survival = self.require::<Survival>(id)?.clone();
+        survival.food = (survival.food - 1).max(0);
+        let starving = survival.food == 0;
+        if !starving {
+            survival.stamina = (survival.stamina + STAMINA_REGEN).min(MAX_STAMINA);
+        }
+        let heal = survival.food >= REGEN_FOOD && survival.stamina == MAX_STAMINA;
+        self.set(id, survival);
+        if heal {
+            self.modify(id, |health: &mut Health| health.hp = (health.hp + 1).min(health.max));
+        }
+        if starving {
+            self.hurt(evs, id, 1)?;
+        }
+        self.schedule(SURVIVAL_INTERVAL, WorldEvent::SurvivalTick(id));
+        Ok(())
+    }
+    /// Spend stamina, or return false if there is not enough. Entities without survival stats
+    /// never run out.
+    pub(super) fn use_stamina(&mut self, id: EntityId, amount: i64) -> bool {
+        match self.get::<Survival>(id) {
+            None => true,
+            Some(Survival { stamina, .. }) if *stamina < amount => false,
+            Some(_) => {
+                self.modify(id, |survival: &mut Survival| survival.stamina -= amount);
+                true
+            }
+        }
+    }
+    /// Eat one item from inventory slot `ix`, which has to hold food.
+    pub(super) fn eat(&mut self, id: EntityId, ix: usize) -> Result<(), WorldError> {
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        let mut survival = self.require::<Survival>(id)?.clone();
+        let food = inventory.get(ix).and_then(|item| item.kind.food_value()).ok_or(WorldError::InvalidState)?;
+        inventory.take_one(ix);
+        survival.food = (survival.food + food).min(MAX_FOOD);
+        self.set(id, inventory);
+        self.set(id, survival);
+        Ok(())
+    }
+}
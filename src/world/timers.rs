@@ -0,0 +1,43 @@
+use rpds::RedBlackTreeMap as Map;
+use archery::shared_pointer::kind::ArcK;
+use serde::{Serialize, Deserialize};
+use std::vec;
+use super::WorldEvent;
+
+/// Events waiting for the world clock to reach their time.
+///
+/// Timers are keyed by the tick they fire at and a sequence number, so timers
+/// for the same tick fire in the order they were scheduled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerQueue {
+    timers: Map<(u64, u64), WorldEvent, ArcK>,
+    next_seq: u64,
+}
+
+impl Default for TimerQueue {
+    fn default() -> TimerQueue {
+        TimerQueue {
+            timers: Map::new_with_ptr_kind(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl TimerQueue {
+    pub fn schedule(&mut self, at: u64, ev: WorldEvent) {
+        self.timers.insert_mut((at, self.next_seq), ev);
+        self.next_seq += 1;
+    }
+    /// Remove and return every event due at or before `now`, in firing order.
+    pub fn take_due(&mut self, now: u64) -> vec::Vec<WorldEvent> {
+        let mut due = vec::Vec::new();
+        while let Some((&key, ev)) = self.timers.first() {
+            if key.0 > now {
+                break;
+            }
+            due.push(ev.clone());
+            self.timers.remove_mut(&key);
+        }
+        due
+    }
+}
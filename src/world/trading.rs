@@ -0,0 +1,157 @@
+use serde::{Serialize, Deserialize};
+use std::vec;
+use super::*;
+
+/// A trade between two adjacent players. Offered items are held outside the players'
+/// inventories until the trade completes or is cancelled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    /// The player who proposed the trade, then the one asked.
+    pub parties: [EntityId; 2],
+    /// Set once the asked player agrees; until then nothing can be offered.
+    pub open: bool,
+    pub offers: [Inventory; 2],
+    /// Reset whenever either offer changes, so nobody confirms a deal they have not seen.
+    pub confirmed: [bool; 2],
+}
+
+impl Trade {
+    /// Which side of the trade the entity is on, as an index into the other fields.
+    pub fn side(&self, id: EntityId) -> Option<usize> {
+        self.parties.iter().position(|party| *party == id)
+    }
+}
+
+impl World {
+    /// The trade the entity takes part in, whether proposed or open.
+    pub fn trade_of(&self, id: EntityId) -> Option<&Trade> {
+        self.trades.values().find(|trade| trade.side(id).is_some())
+    }
+    /// Trades are keyed by the player who proposed them.
+    fn trade_key(&self, id: EntityId) -> Option<EntityId> {
+        self.trade_of(id).map(|trade| trade.parties[0])
+    }
+    /// Ask the player next to the entity in `dir` to trade, or accept if they asked first.
+    pub(super) fn propose_trade(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, dir: Dir) -> Result<(), WorldError> {
+        let pos = *self.require::<Vec>(id)? + dir.to_vec();
+        let other = self.query_at::<&EntityKind>(pos)
+            .find(|(oid, kind)| matches!(kind, EntityKind::Player(_)) && *oid != id)
+            .map(|(oid, _)| oid);
+        let other = match other {
+            Some(other) => other,
+            None => {
+                evs.push(WorldEvent::Notify(id, "There is nobody there to trade with.".to_string()));
+                return Ok(());
+            }
+        };
+        if let Some(trade) = self.trades.get(&other) {
+            if trade.parties[1] == id && !trade.open {
+                let mut trade = trade.clone();
+                trade.open = true;
+                self.trades.insert_mut(other, trade);
+                evs.push(WorldEvent::Notify(other, "The other player agreed to trade.".to_string()));
+                evs.push(WorldEvent::Notify(id, "You agreed to trade.".to_string()));
+                return Ok(());
+            }
+        }
+        if self.trade_of(id).is_some() {
+            evs.push(WorldEvent::Notify(id, "You are already trading.".to_string()));
+            return Ok(());
+        }
+        if self.trade_of(other).is_some() {
+            evs.push(WorldEvent::Notify(id, "That player is busy trading.".to_string()));
+            return Ok(());
+        }
+        let offer = Inventory::new(PLAYER_SLOTS, None);
+        self.trades.insert_mut(id, Trade {
+            parties: [id, other],
+            open: false,
+            offers: [offer.clone(), offer],
+            confirmed: [false, false],
+        });
+        evs.push(WorldEvent::Notify(id, "You asked the other player to trade.".to_string()));
+        evs.push(WorldEvent::Notify(other, "Another player wants to trade. Press t towards them to agree.".to_string()));
+        Ok(())
+    }
+    /// Move up to `count` items from inventory slot `ix` into the entity's offer.
+    pub(super) fn offer_items(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId, ix: usize, count: usize) -> Result<(), WorldError> {
+        let key = self.trade_key(id).ok_or(WorldError::InvalidState)?;
+        let mut trade = self.trades.get(&key).ok_or(WorldError::InvalidState)?.clone();
+        let side = trade.side(id).ok_or(WorldError::InvalidState)?;
+        if !trade.open {
+            return Err(WorldError::InvalidState);
+        }
+        let mut inventory = self.require::<Inventory>(id)?.clone();
+        let (item, count) = inventory.take(ix, count).ok_or(WorldError::OutOfBounds)?;
+        if !trade.offers[side].insert_stack(&item, count) {
+            evs.push(WorldEvent::Notify(id, "Your offer is full.".to_string()));
+            return Ok(());
+        }
+        trade.confirmed = [false, false];
+        self.trades.insert_mut(key, trade);
+        self.set(id, inventory);
+        Ok(())
+    }
+    /// Agree to the current offers. Once both players have, the offers change hands at once.
+    pub(super) fn confirm_trade(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId) -> Result<(), WorldError> {
+        let key = self.trade_key(id).ok_or(WorldError::InvalidState)?;
+        let mut trade = self.trades.get(&key).ok_or(WorldError::InvalidState)?.clone();
+        let side = trade.side(id).ok_or(WorldError::InvalidState)?;
+        if !trade.open {
+            return Err(WorldError::InvalidState);
+        }
+        trade.confirmed[side] = true;
+        let [a, b] = trade.parties;
+        if trade.confirmed != [true, true] {
+            self.trades.insert_mut(key, trade);
+            let other = if side == 0 { b } else { a };
+            evs.push(WorldEvent::Notify(other, "The other player confirmed the trade.".to_string()));
+            return Ok(());
+        }
+        let (mut inventory_a, mut inventory_b) = (self.require::<Inventory>(a)?.clone(), self.require::<Inventory>(b)?.clone());
+        let (mut to_a, mut to_b) = (trade.offers[1].clone(), trade.offers[0].clone());
+        inventory_a.insert_inventory(&mut to_a);
+        inventory_b.insert_inventory(&mut to_b);
+        if !to_a.is_empty() || !to_b.is_empty() {
+            trade.confirmed = [false, false];
+            self.trades.insert_mut(key, trade);
+            for party in [a, b] {
+                evs.push(WorldEvent::Notify(party, "There is not enough room for the trade.".to_string()));
+            }
+            return Ok(());
+        }
+        self.trades.remove_mut(&key);
+        self.set(a, inventory_a);
+        self.set(b, inventory_b);
+        for party in [a, b] {
+            evs.push(WorldEvent::Notify(party, "Trade complete.".to_string()));
+        }
+        Ok(())
+    }
+    /// End the entity's trade, if any, giving each player back what they offered.
+    /// Returns whether there was a trade to cancel.
+    pub(super) fn cancel_trade(&mut self, evs: &mut vec::Vec<WorldEvent>, id: EntityId) -> bool {
+        let key = match self.trade_key(id) {
+            Some(key) => key,
+            None => return false,
+        };
+        let trade = match self.trades.get(&key) {
+            Some(trade) => trade.clone(),
+            None => return false,
+        };
+        self.trades.remove_mut(&key);
+        for (party, mut offer) in trade.parties.iter().copied().zip(trade.offers.iter().cloned()) {
+            if let Some(inventory) = self.get::<Inventory>(party) {
+                let mut inventory = inventory.clone();
+                inventory.insert_inventory(&mut offer);
+                self.set(party, inventory);
+            }
+            // Whatever no longer fits ends up at the player's feet.
+            if let (false, Some(pos)) = (offer.is_empty(), self.get::<Vec>(party)) {
+                evs.push(offer.drop(*pos));
+            }
+            evs.push(WorldEvent::Notify(party, "The trade was cancelled.".to_string()));
+        }
+        true
+    }
+}
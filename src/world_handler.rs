@@ -2,64 +2,139 @@ use crossbeam::channel;
 use crossbeam::channel::select;
 use crate::terminal;
 use crate::world::*;
+use crate::world::crafting::RecipeId;
+use crate::content::content;
+use crate::world::equipment::EquipSlot;
 use crate::geom::*;
 use crate::{WorldIOHalf, ClientId, ToClientEvent, FromClientEvent, gen_event_id};
 use crate::renderer;
 use std::thread;
 use std::vec;
 use std::time::{Instant, Duration};
+use std::fs::OpenOptions;
+use std::io::Write;
 
-pub fn handle_world(world_io: WorldIOHalf, start_world: World, me: ClientId) {
-    let (uitx, uirx) = channel::unbounded::<WorldEvent>();
-    let mut uitx = Some(uitx);
+/// What the input thread asks of the game loop.
+enum UiCommand {
+    Action(PlayerActionEvent),
+    Leave,
+    Rejoin,
+    Quit,
+    Shutdown,
+}
+
+pub fn handle_world(world_io: WorldIOHalf, start_world: World, me: ClientId, hosting: bool) {
+    let (uitx, uirx) = channel::unbounded::<UiCommand>();
+    start_ui_input(uitx, world_io.term.clone(), hosting);
     let mut self_entity = None;
+    let mut left = false;
     let mut agreed_world = start_world.clone();
     let mut speculative_world = start_world;
     let mut awaiting_events = vec::Vec::new();
     let start_time = Instant::now();
     let mut est_delta = Duration::new(0, 0);
+    // Set while waiting for the host's copy of the world after a desync.
+    let mut resyncing = false;
     loop {
         select! {
             recv(uirx) -> msg => { // speculative evaluation, TODO
-                    let msg = msg.unwrap();
+                    let msg = match (msg.unwrap(), self_entity) {
+                        (UiCommand::Action(action), Some(entity)) => WorldEvent::PlayerAction(entity, action),
+                        (UiCommand::Action(_), None) => continue, // dead or left
+                        (UiCommand::Leave, _) => {
+                            left = true;
+                            let _ = world_io.send.send(FromClientEvent::Leave());
+                            continue;
+                        }
+                        (UiCommand::Rejoin, _) => {
+                            left = false;
+                            let _ = world_io.send.send(FromClientEvent::Rejoin());
+                            continue;
+                        }
+                        (UiCommand::Quit, _) => {
+                            let _ = world_io.send.send(FromClientEvent::Disconnect());
+                            if hosting {
+                                let _ = world_io.term.println("The server keeps running in the background. Press K to shut it down.");
+                                // Nothing else can be asked of the server once we have disconnected.
+                                if let Ok(UiCommand::Shutdown) = uirx.recv() {
+                                    let _ = world_io.send.send(FromClientEvent::Shutdown());
+                                }
+                            }
+                            thread::sleep(Duration::from_millis(100));
+                            return;
+                        }
+                        (UiCommand::Shutdown, _) => {
+                            let _ = world_io.send.send(FromClientEvent::Shutdown());
+                            continue; // the host kicks us once it has shut down
+                        }
+                    };
+                    // Actions the world rejects, like using an empty slot, would be dropped by the host anyway.
+                    // Entities our action creates get provisional ids until the host's arrive.
+                    let next_world = match speculative_world.apply(Some(me), msg.clone(), true) {
+                        Ok(next_world) => next_world,
+                        Err(_) => continue,
+                    };
                     let id = gen_event_id();
                     let ev = FromClientEvent::PlayerEvent(id, msg.clone());
                     world_io.send.send(ev).unwrap();
-                    awaiting_events.push((Instant::now() - start_time, id, Some(me), msg.clone()));
-                    speculative_world = speculative_world.handle_event(Some(me), msg).unwrap().0; // TODO: save speculative auto events
+                    awaiting_events.push((Instant::now() - start_time, id, Some(me), msg));
+                    speculative_world = next_world;
                 },
             recv(world_io.recv) -> msg => { // definitive evaluation
                 let msg = msg.unwrap();
-                match (&mut uitx, &msg) {
-                    (None, _) => {}
-                    (uitx, (_, ToClientEvent::WorldEvent(_, None, WorldEvent::SpawnEntity(id, entity))))
-                        if entity.is_player(me) => {
-                        self_entity = Some(id.clone());
-                        start_ui_input(*id, uitx.take().unwrap(), world_io.term.clone());
-                    }
-                    _ => {}
-                }
                 match (&self_entity, &msg) {
                     (None, _) => {}
                     (Some(self_id), (_, ToClientEvent::WorldEvent(_, _, WorldEvent::DeleteEntity(id)))) if self_id == id => {
-                        world_io.send.send(FromClientEvent::Disconnect());
-                        world_io.term.println("You died!");
-                        thread::sleep(Duration::from_millis(100));
-                        return;
+                        self_entity = None;
+                        if left {
+                            let _ = world_io.term.println("You left the game. Press Q to open the menu and rejoin.");
+                        } else {
+                            let _ = world_io.term.println("You died!");
+                        }
+                    }
+                    (Some(self_id), (_, ToClientEvent::WorldEvent(_, _, WorldEvent::Notify(id, text)))) if self_id == id => {
+                        let _ = world_io.term.println(text);
                     }
                     _ => {}
                 }
                 match msg {
                     (_, ToClientEvent::NewClientId(_)) => {}
                     (_, ToClientEvent::RemoveClientId(_)) => {}
+                    (_, ToClientEvent::YourEntity(id)) => self_entity = Some(id),
                     (_, ToClientEvent::Kick(reason)) => {
                         world_io.term.println(format!("You have been kicked: {}", reason)).unwrap();
                         return;
                     }
+                    (_, ToClientEvent::Resync(world)) => {
+                        agreed_world = *world;
+                        speculative_world = agreed_world.clone();
+                        awaiting_events.clear();
+                        self_entity = agreed_world.player_entity(me);
+                        resyncing = false;
+                    }
+                    (_, ToClientEvent::Checksum(evid, time, checksum)) => {
+                        let ours = agreed_world.checksum();
+                        if !resyncing && (agreed_world.time() != time || ours != checksum) {
+                            log_desync(&world_io.term, format!("Desync after event {:?}: host is at tick {} with checksum {:016x}, we are at tick {} with checksum {:016x}.",
+                                evid, time, checksum, agreed_world.time(), ours));
+                            let _ = world_io.send.send(FromClientEvent::Resync());
+                            resyncing = true;
+                        }
+                    }
+                    // Everything up to the host's copy of the world is already part of it.
+                    (_, ToClientEvent::WorldEvent(..)) if resyncing => {}
                     (time, ToClientEvent::WorldEvent(evid, owner, ev)) => {
-                        let (new_world, mut pending_events) = agreed_world.handle_event(owner, ev).unwrap();
-                        agreed_world = new_world;
-                        pending_events.sort_by_key(|(time, _)| std::cmp::Reverse(*time));
+                        // Follow-up events are sent to us by the host, so they are not applied here.
+                        // The host applied this event, so failing here means our copy of the world has diverged.
+                        agreed_world = match agreed_world.handle_event(owner, ev.clone()) {
+                            Ok((new_world, _)) => new_world,
+                            Err(error) => {
+                                log_desync(&world_io.term, format!("Desync: host applied {:?} from {:?}, we rejected it: {}", ev, owner, error));
+                                let _ = world_io.send.send(FromClientEvent::Resync());
+                                resyncing = true;
+                                continue;
+                            }
+                        };
                         speculative_world = agreed_world.clone();
                         if owner == Some(me) {
                             let mut iter = awaiting_events.into_iter().skip_while(|(_, id, _, _)| *id != evid).fuse();
@@ -75,12 +150,22 @@ pub fn handle_world(world_io: WorldIOHalf, start_world: World, me: ClientId) {
                             // what if there are multiple events happening at the same time?
                             awaiting_events = awaiting_events.into_iter().skip_while(|(offset, _, _, _)| *offset + est_delta < time).collect();
                         }
-                        for (offset, _, owner, ev) in awaiting_events.iter().take_while(|(offset, _, _, _)| *offset < Instant::now() - start_time) {
-                            speculative_world = speculative_world.handle_event(*owner, ev.clone()).unwrap().0; // TODO: save speculative auto events
+                        for (_, _, owner, ev) in awaiting_events.iter().take_while(|(offset, _, _, _)| *offset < Instant::now() - start_time) {
+                            if let Ok(next_world) = speculative_world.apply(*owner, ev.clone(), true) {
+                                speculative_world = next_world;
+                            }
                         }
-                        match &self_entity {
-                            None => {}
-                            Some(entity) => render_world(&speculative_world, entity, &world_io.term)
+                        match (&self_entity, agreed_world.respawn_time(me)) {
+                            (Some(entity), _) => render_world(&speculative_world, entity, &world_io.term),
+                            (None, Some(at)) => {
+                                let scene = renderer::render_death_screen(at.saturating_sub(agreed_world.time()));
+                                world_io.term.draw_scene(scene).unwrap();
+                            }
+                            (None, None) if left => {
+                                let scene = renderer::render_message(&["You left the game.".to_string(), "Press Q for the menu.".to_string()]);
+                                world_io.term.draw_scene(scene).unwrap();
+                            }
+                            (None, None) => {}
                         }
                     }
                 }
@@ -94,23 +179,223 @@ fn render_world(world: &World, player: &EntityId, term: &terminal::Terminal) {
     term.draw_scene(scene).unwrap();
 }
 
-fn start_ui_input(entity: EntityId, uitx: channel::Sender<WorldEvent>, term: terminal::Terminal) {
+/// Read keys and turn them into actions for whichever entity the player currently controls.
+fn start_ui_input(uitx: channel::Sender<UiCommand>, term: terminal::Terminal, hosting: bool) {
     thread::spawn (move || {
-        term.println("Use WASD to move.");
+        let _ = term.println("Use WASD to move, C to craft, E to equip, U to unequip, B to build, F to eat, G to drop, V to give, X to split, M to merge, T to trade, O to offer, Y to confirm a trade, N to cancel it, Q for the menu.");
         loop {
-            let ev = term.get_ev().unwrap();
+            let ev = match term.get_ev() {
+                Ok(ev) => ev,
+                Err(_) => return,
+            };
             use termion::event::*;
             match ev {
+                Event::Key(Key::Char('q')) => {
+                    if let Some(cmd) = choose_menu(&term, hosting) {
+                        let quit = matches!(cmd, UiCommand::Quit);
+                        if uitx.send(cmd).is_err() {
+                            return;
+                        }
+                        if quit {
+                            if hosting {
+                                wait_for_shutdown(&uitx, &term);
+                            }
+                            return;
+                        }
+                    }
+                }
                 Event::Key(Key::Char(ch)) if is_wasd(ch) =>
-                    uitx.send(WorldEvent::PlayerAction(entity, PlayerActionEvent::Move(wasd_to_dir(ch)))).unwrap(),
+                    uitx.send(UiCommand::Action(PlayerActionEvent::Move(wasd_to_dir(ch)))).unwrap(),
                 Event::Key(Key::Char(ch)) if is_wasd(ch.to_ascii_lowercase()) =>
-                    uitx.send(WorldEvent::PlayerAction(entity, PlayerActionEvent::Attack(wasd_to_dir(ch.to_ascii_lowercase())))).unwrap(),
+                    uitx.send(UiCommand::Action(PlayerActionEvent::Attack(wasd_to_dir(ch.to_ascii_lowercase())))).unwrap(),
+                Event::Key(Key::Char('c')) => {
+                    if let Some(recipe) = choose_recipe(&term) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Craft(recipe))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('e')) => {
+                    let _ = term.println("Equip which item? (1-9)");
+                    if let Some(ix) = choose_number(&term) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Equip(ix))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('u')) => {
+                    let _ = term.println("Put away what? (H)and or (B)ody");
+                    if let Some(slot) = choose_slot(&term) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Unequip(slot))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('g')) => {
+                    let _ = term.println("Drop which item? (1-9)");
+                    if let (Some(ix), Some(count)) = (choose_number(&term), choose_count(&term)) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Drop(ix, count))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('v')) => {
+                    let _ = term.println("Give which item? (1-9)");
+                    if let (Some(ix), Some(count)) = (choose_number(&term), choose_count(&term)) {
+                        let _ = term.println("Give to whom? (WASD)");
+                        if let Some(dir) = choose_dir(&term) {
+                            uitx.send(UiCommand::Action(PlayerActionEvent::Give(ix, count, dir))).unwrap();
+                        }
+                    }
+                }
+                Event::Key(Key::Char('f')) => {
+                    let _ = term.println("Eat which item? (1-9)");
+                    if let Some(ix) = choose_number(&term) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Eat(ix))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('b')) => {
+                    let _ = term.println("Build with which item? (1-9)");
+                    if let Some(ix) = choose_number(&term) {
+                        let _ = term.println("Build where? (WASD)");
+                        if let Some(dir) = choose_dir(&term) {
+                            uitx.send(UiCommand::Action(PlayerActionEvent::Build(ix, dir))).unwrap();
+                        }
+                    }
+                }
+                Event::Key(Key::Char('x')) => {
+                    let _ = term.println("Split which stack? (1-9)");
+                    if let (Some(ix), Some(count)) = (choose_number(&term), choose_count(&term)) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Split(ix, count))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('t')) => {
+                    let _ = term.println("Trade with whom? (WASD)");
+                    if let Some(dir) = choose_dir(&term) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Trade(dir))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('o')) => {
+                    let _ = term.println("Offer which item? (1-9)");
+                    if let (Some(ix), Some(count)) = (choose_number(&term), choose_count(&term)) {
+                        uitx.send(UiCommand::Action(PlayerActionEvent::Offer(ix, count))).unwrap();
+                    }
+                }
+                Event::Key(Key::Char('y')) =>
+                    uitx.send(UiCommand::Action(PlayerActionEvent::ConfirmTrade)).unwrap(),
+                Event::Key(Key::Char('n')) =>
+                    uitx.send(UiCommand::Action(PlayerActionEvent::CancelTrade)).unwrap(),
+                Event::Key(Key::Char('m')) => {
+                    let _ = term.println("Merge which stack? (1-9)");
+                    if let Some(from) = choose_number(&term) {
+                        let _ = term.println("Into which stack? (1-9)");
+                        if let Some(into) = choose_number(&term) {
+                            uitx.send(UiCommand::Action(PlayerActionEvent::Merge(from, into))).unwrap();
+                        }
+                    }
+                }
                 _ => ()
             }
         }
     });
 }
 
+/// Where the details of each desync are written, since printing them would garble the game screen.
+const DESYNC_LOG: &str = "desync.log";
+
+/// Record what went out of sync and tell the player we are fetching the host's world.
+fn log_desync(term: &terminal::Terminal, details: String) {
+    let logged = OpenOptions::new().create(true).append(true).open(DESYNC_LOG)
+        .and_then(|mut file| writeln!(file, "{}", details));
+    let _ = match logged {
+        Ok(()) => term.println(format!("Out of sync with the server, fetching its world. Details are in {}.", DESYNC_LOG)),
+        Err(_) => term.println("Out of sync with the server, fetching its world."),
+    };
+}
+
+/// After the host's player has quit, the server can still be shut down by pressing K.
+fn wait_for_shutdown(uitx: &channel::Sender<UiCommand>, term: &terminal::Terminal) {
+    use termion::event::*;
+    while let Ok(ev) = term.get_ev() {
+        if let Event::Key(Key::Char('k')) | Event::Key(Key::Char('K')) = ev {
+            let _ = uitx.send(UiCommand::Shutdown);
+            return;
+        }
+    }
+}
+
+/// Show the game menu and wait for the player to pick an entry.
+fn choose_menu(term: &terminal::Terminal, hosting: bool) -> Option<UiCommand> {
+    let _ = term.println("Menu: (Esc to resume)");
+    let _ = term.println("l) Leave the game");
+    let _ = term.println("r) Rejoin the game");
+    if hosting {
+        let _ = term.println("q) Quit, keeping the server running");
+        let _ = term.println("k) Shut down the server");
+    } else {
+        let _ = term.println("q) Disconnect");
+    }
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char('l')) => Some(UiCommand::Leave),
+        Event::Key(Key::Char('r')) => Some(UiCommand::Rejoin),
+        Event::Key(Key::Char('q')) => Some(UiCommand::Quit),
+        Event::Key(Key::Char('k')) if hosting => Some(UiCommand::Shutdown),
+        _ => None,
+    }
+}
+
+/// Show the recipe list and wait for the player to pick one.
+fn choose_recipe(term: &terminal::Terminal) -> Option<RecipeId> {
+    let _ = term.println("Craft what? (Esc to cancel)");
+    for (ix, recipe) in content().recipes.iter().enumerate() {
+        let _ = term.println(format!("{}) {}", recipe_key(ix), recipe.describe()));
+    }
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char(ch)) => (0 .. content().recipes.len())
+            .find(|&ix| recipe_key(ix) == ch)
+            .map(RecipeId),
+        _ => None,
+    }
+}
+
+/// Recipes are picked by letter, since there are more of them than digits. The content file
+/// holds at most `content::MAX_RECIPES` of them, one per letter.
+fn recipe_key(ix: usize) -> char {
+    (b'a' + ix as u8) as char
+}
+
+/// Wait for a digit key and turn it into a zero-based index.
+fn choose_number(term: &terminal::Terminal) -> Option<usize> {
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char(ch)) => ch.to_digit(10)
+            .and_then(|n| (n as usize).checked_sub(1)),
+        _ => None,
+    }
+}
+
+/// Ask how many items to use; 0 stands for the whole stack.
+fn choose_count(term: &terminal::Terminal) -> Option<usize> {
+    let _ = term.println("How many? (1-9, 0 for all)");
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char('0')) => Some(usize::MAX),
+        Event::Key(Key::Char(ch)) => ch.to_digit(10).map(|n| n as usize),
+        _ => None,
+    }
+}
+
+fn choose_slot(term: &terminal::Terminal) -> Option<EquipSlot> {
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char('h')) => Some(EquipSlot::Hand),
+        Event::Key(Key::Char('b')) => Some(EquipSlot::Body),
+        _ => None,
+    }
+}
+
+fn choose_dir(term: &terminal::Terminal) -> Option<Dir> {
+    use termion::event::*;
+    match term.get_ev().unwrap() {
+        Event::Key(Key::Char(ch)) if is_wasd(ch) => Some(wasd_to_dir(ch)),
+        _ => None,
+    }
+}
+
 fn is_wasd(ch: char) -> bool {
     ch == 'w' || ch == 'a' || ch == 's' || ch == 'd'
 }
@@ -0,0 +1,61 @@
+//! Hits between players and creatures: deaths, loot and knockback.
+
+mod common;
+
+use rust_game::ClientId;
+use rust_game::content::{self, CONTENT_PATH};
+use rust_game::geom::{Vec, Dir};
+use rust_game::world::{World, WorldEvent, Entity, EntityKind, Health, PlayerActionEvent};
+use rust_game::world::creatures::{CreatureKind, Reaction};
+use rust_game::world::PlayerActionEvent::Attack;
+use common::{spawn, count_events, generated_world};
+
+#[test]
+fn entities_die_only_once() {
+    content::load(CONTENT_PATH).unwrap();
+    let wolf = CreatureKind::all().find(|kind| kind.def().reaction == Reaction::Chase).expect("no creature hunts players");
+    let player = |client, hp, pos| Entity::player(client, pos).with(Health { hp, max: 10 });
+
+    // Two wolves bite a player on their last hit point before the player is deleted.
+    let world = World::default();
+    let (world, victim) = spawn(&world, player(ClientId::from(0), 1, Vec::new(0, 0)));
+    let (world, left) = spawn(&world, wolf.create(Vec::new(-1, 0)));
+    let (world, right) = spawn(&world, wolf.create(Vec::new(1, 0)));
+    let (world, mut evs) = world.handle_event(None, WorldEvent::CreatureThink(left)).unwrap();
+    evs.extend(world.handle_event(None, WorldEvent::CreatureThink(right)).unwrap().1);
+    assert_eq!(count_events(&evs, |ev| matches!(ev, WorldEvent::DeleteEntity(id) if *id == victim)), 1);
+
+    // Two players hit a wolf on its last hit point, which drops its loot once.
+    let world = World::default();
+    let (mut world, prey) = spawn(&world, wolf.create(Vec::new(0, 0)).with(Health { hp: 1, max: 5 }));
+    let mut evs = vec![];
+    for (client, x) in &[(ClientId::from(0), -1), (ClientId::from(1), 1)] {
+        let (next, id) = spawn(&world, player(*client, 10, Vec::new(*x, 0)));
+        let dir = *Dir::all().iter().find(|dir| Vec::new(*x, 0) + dir.to_vec() == Vec::new(0, 0)).unwrap();
+        let (next, more) = next.handle_event(Some(*client), WorldEvent::PlayerAction(id, PlayerActionEvent::Attack(dir))).unwrap();
+        world = next;
+        evs.extend(more);
+    }
+    assert_eq!(count_events(&evs, |ev| matches!(ev, WorldEvent::DeleteEntity(id) if *id == prey)), 1);
+    assert_eq!(count_events(&evs, |ev| matches!(ev, WorldEvent::CreateEntity(entity) if entity.kind == EntityKind::Treasure)), 1);
+}
+
+#[test]
+fn knockback_leads_through_entrances() {
+    content::load(CONTENT_PATH).unwrap();
+    let world = generated_world();
+    let free = |pos: Vec| world.tiles.get(pos).is_free() && world.linked_entrance(pos).is_none();
+    // A creature stands between a player and an entrance on the surface.
+    let (target, dir, other) = (-60 .. 60).flat_map(|x| (-60 .. 60).map(move |y| Vec::new(x, y)))
+        .filter_map(|pos| world.linked_entrance(pos).map(|other| (pos, other)))
+        .find_map(|(pos, other)| Dir::all().iter()
+            .find(|dir| free(pos - dir.to_vec()))
+            .map(|dir| (pos - dir.to_vec(), *dir, other)))
+        .expect("no entrance near the start");
+    let client = ClientId::from(0);
+    let deer = CreatureKind::all().find(|kind| kind.def().reaction == Reaction::Flee).expect("no creature flees");
+    let (world, player) = spawn(&world, Entity::player(client, target - dir.to_vec()));
+    let (world, prey) = spawn(&world, deer.create(target));
+    let world = world.apply(Some(client), WorldEvent::PlayerAction(player, Attack(dir)), false).unwrap();
+    assert_eq!(world.get::<Vec>(prey), Some(&other));
+}
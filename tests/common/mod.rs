@@ -0,0 +1,47 @@
+//! Helpers shared by the integration tests. Not every test file uses all of them.
+#![allow(dead_code)]
+
+use rust_game::ClientId;
+use rust_game::geom::Vec;
+use rust_game::world::{World, WorldEvent, Entity, EntityId, Inventory, Item, ItemKind, PLAYER_SLOTS, PLAYER_MAX_WEIGHT};
+
+/// A new world with the chunks around the start generated, as they are once the first player has
+/// joined. Elsewhere the tiles are blank until a player comes near.
+pub fn generated_world() -> World {
+    let world = World::default();
+    let world = world.apply(None, world.create_player_spawn_event(ClientId::from(u64::MAX)), false).unwrap();
+    let id = world.player_entity(ClientId::from(u64::MAX)).unwrap();
+    world.apply(None, WorldEvent::DeleteEntity(id), false).unwrap()
+}
+
+/// Spawn an entity the way the host does, returning its id. Follow-ups are not applied.
+pub fn spawn(world: &World, entity: Entity) -> (World, EntityId) {
+    match world.assign_id(WorldEvent::CreateEntity(entity), false) {
+        ev @ WorldEvent::SpawnEntity(id, _) => (world.handle_event(None, ev).unwrap().0, id),
+        _ => panic!("creation events should become spawn events"),
+    }
+}
+
+/// Spawn a player for `client` at `pos` carrying `stacks`, returning its id.
+pub fn spawn_player(world: &World, client: ClientId, pos: Vec, stacks: &[(&str, usize)]) -> (World, EntityId) {
+    let mut inventory = Inventory::new(PLAYER_SLOTS, Some(PLAYER_MAX_WEIGHT));
+    for (name, count) in stacks {
+        assert!(inventory.insert_stack(&item(name), *count), "{} {} does not fit", count, name);
+    }
+    spawn(world, Entity::player(client, pos).with(inventory))
+}
+
+/// An item of the kind with the given display name.
+pub fn item(name: &str) -> Item {
+    let kind = ItemKind::all().find(|kind| kind.name() == name).unwrap_or_else(|| panic!("no item `{}`", name));
+    Item::new(kind)
+}
+
+/// The stacks in the entity's inventory, by item name.
+pub fn stacks(world: &World, id: EntityId) -> std::vec::Vec<(&'static str, usize)> {
+    world.get::<Inventory>(id).unwrap().items().map(|(item, count)| (item.kind().name(), count)).collect()
+}
+
+pub fn count_events(evs: &[WorldEvent], pred: impl Fn(&WorldEvent) -> bool) -> usize {
+    evs.iter().filter(|ev| pred(ev)).count()
+}
@@ -0,0 +1,80 @@
+//! Loads edited copies of the shipped content file, checking that bad values are caught
+//! with the line they are on rather than breaking the game later.
+
+use rust_game::content::{self, ContentError, CONTENT_PATH, MAX_RECIPES};
+
+/// Load the shipped content with `edit` applied to its text, returning the error.
+fn load_edited(name: &str, edit: impl FnOnce(String) -> String) -> ContentError {
+    let text = std::fs::read_to_string(CONTENT_PATH).unwrap();
+    let path = std::env::temp_dir().join(format!("rust_game_content_{}.txt", name));
+    std::fs::write(&path, edit(text)).unwrap();
+    let result = content::load(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    result.expect_err("the edited content should be rejected")
+}
+
+/// The 1-based line of the first line that is exactly `line` after the line starting `section`.
+fn line_of(section: &str, line: &str) -> usize {
+    let text = std::fs::read_to_string(CONTENT_PATH).unwrap();
+    let start = text.lines().position(|l| l.starts_with(section)).unwrap();
+    start + text.lines().skip(start).position(|l| l == line).unwrap() + 1
+}
+
+/// Replace the first `from` line after the line starting `section` with `to`.
+fn replace_in(section: &'static str, from: &'static str, to: &'static str) -> impl FnOnce(String) -> String {
+    move |text| {
+        let start = text.lines().position(|l| l.starts_with(section)).unwrap();
+        let mut done = false;
+        text.lines().enumerate()
+            .map(|(ix, l)| if !done && ix >= start && l == from { done = true; to } else { l })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[test]
+fn shipped_content_loads() {
+    content::load(CONTENT_PATH).unwrap();
+}
+
+#[test]
+fn zero_light_radius_is_rejected() {
+    let err = load_edited("light", replace_in("[terrain campfire]", "light = 7", "light = 0"));
+    assert_eq!(err.line, Some(line_of("[terrain campfire]", "light = 7")));
+    assert!(err.message.contains("more than 0"), "{}", err);
+
+    let err = load_edited("item_light", replace_in("[item torch]", "light = 4", "light = -1"));
+    assert_eq!(err.line, Some(line_of("[item torch]", "light = 4")));
+}
+
+#[test]
+fn zero_stack_is_rejected() {
+    let err = load_edited("stack", replace_in("[item log]", "stack = 16", "stack = 0"));
+    assert_eq!(err.line, Some(line_of("[item log]", "stack = 16")));
+    assert!(err.message.contains("more than 0"), "{}", err);
+}
+
+#[test]
+fn durable_items_cannot_stack() {
+    let err = load_edited("durable_stack", replace_in("[item wooden_club]", "weight = 2", "stack = 4\nweight = 2"));
+    assert_eq!(err.line, Some(line_of("[item wooden_club]", "weight = 2")));
+    assert!(err.message.contains("cannot stack"), "{}", err);
+}
+
+#[test]
+fn creatures_need_health() {
+    let err = load_edited("hp", replace_in("[creature wolf]", "hp = 5", "hp = 0"));
+    assert_eq!(err.line, Some(line_of("[creature wolf]", "hp = 5")));
+}
+
+#[test]
+fn recipes_are_limited_to_one_per_letter() {
+    let err = load_edited("recipes", |mut text| {
+        for ix in 0..MAX_RECIPES {
+            text += &format!("\n[recipe extra_{}]\ninputs = log 1\noutputs = plank 1\n", ix);
+        }
+        text
+    });
+    assert!(err.line.is_some());
+    assert!(err.message.contains("at most"), "{}", err);
+}
@@ -0,0 +1,209 @@
+//! Drives random event sequences through `World::handle_event`, the way the host does.
+//! Bad events have to be rejected with a `WorldError` rather than a panic, and replaying
+//! the accepted events has to give the same world, since every client relies on that.
+//! Each client is also sent its own view of the events, which it has to be able to follow.
+
+use std::collections::VecDeque;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rust_game::ClientId;
+use rust_game::host::view::View;
+use rust_game::content::{self, CONTENT_PATH};
+use rust_game::geom::{Vec, Dir};
+use rust_game::world::{World, WorldEvent, WorldError, EntityId, EntityKind, Inventory, PlayerActionEvent};
+use rust_game::world::crafting::RecipeId;
+use rust_game::world::fov::VIEW_RADIUS;
+use rust_game::world::equipment::EquipSlot;
+
+const CLIENTS: u64 = 3;
+const STEPS: usize = 1500;
+
+fn random_dir(rng: &mut StdRng) -> Dir {
+    Dir::all()[rng.gen_range(0, 4)]
+}
+
+fn random_action(rng: &mut StdRng) -> PlayerActionEvent {
+    let ix = rng.gen_range(0, 12);
+    let count = rng.gen_range(0, 4);
+    match rng.gen_range(0, 15) {
+        0 => PlayerActionEvent::Move(random_dir(rng)),
+        1 => PlayerActionEvent::Attack(random_dir(rng)),
+        2 => PlayerActionEvent::Craft(RecipeId(rng.gen_range(0, 20))),
+        3 => PlayerActionEvent::Equip(ix),
+        4 => PlayerActionEvent::Unequip(if rng.gen() { EquipSlot::Hand } else { EquipSlot::Body }),
+        5 => PlayerActionEvent::Drop(ix, count),
+        6 => PlayerActionEvent::Give(ix, count, random_dir(rng)),
+        7 => PlayerActionEvent::Split(ix, count),
+        8 => PlayerActionEvent::Merge(ix, rng.gen_range(0, 12)),
+        9 => PlayerActionEvent::Build(ix, random_dir(rng)),
+        10 => PlayerActionEvent::Eat(ix),
+        11 => PlayerActionEvent::Trade(random_dir(rng)),
+        12 => PlayerActionEvent::Offer(ix, count),
+        13 => PlayerActionEvent::ConfirmTrade,
+        _ => PlayerActionEvent::CancelTrade,
+    }
+}
+
+/// Mostly ticks and player actions, with the occasional event that refers to a stale entity.
+fn random_event(rng: &mut StdRng, world: &World, max_id: u64) -> (Option<ClientId>, WorldEvent) {
+    let client = ClientId::from(rng.gen_range(0, CLIENTS));
+    let id = EntityId::from(rng.gen_range(0, max_id + 3));
+    let pos = Vec::new(rng.gen_range(-20, 20), rng.gen_range(-20, 20));
+    match rng.gen_range(0, 20) {
+        0 ..= 5 => (None, WorldEvent::Tick),
+        6 ..= 11 => {
+            let id = world.player_entity(client).filter(|_| rng.gen_range(0, 4) > 0).unwrap_or(id);
+            (Some(client), WorldEvent::PlayerAction(id, random_action(rng)))
+        }
+        12 if !world.has_player(client) => (None, world.create_player_spawn_event(client)),
+        12 => (None, world.create_player_exit_event(client).unwrap_or(WorldEvent::Tick)),
+        13 => (None, WorldEvent::Enter(id, pos)),
+        14 => (None, WorldEvent::DeleteEntity(id)),
+        15 => (None, WorldEvent::CreatureThink(id)),
+        16 => (None, WorldEvent::SurvivalTick(id)),
+        17 => (None, WorldEvent::SpawnCreatures),
+        18 => (None, WorldEvent::Respawn(client)),
+        _ => (Some(client), WorldEvent::Tick),
+    }
+}
+
+/// Check that a rejected event was rejected for the right reason.
+fn check_error(world: &World, sender: Option<ClientId>, ev: &WorldEvent, error: WorldError) {
+    let subject = match ev {
+        WorldEvent::PlayerAction(id, _) | WorldEvent::DeleteEntity(id) | WorldEvent::Enter(id, _) |
+        WorldEvent::CreatureThink(id) | WorldEvent::SurvivalTick(id) => Some(*id),
+        _ => None,
+    };
+    let kind = subject.and_then(|id| world.get::<EntityKind>(id));
+    match (sender, ev, subject, kind) {
+        (_, _, Some(id), None) => assert_eq!(error, WorldError::MissingEntity(id), "{:?}", ev),
+        (Some(_), WorldEvent::PlayerAction(..), _, _) => {}
+        (Some(_), _, _, _) => assert_eq!(error, WorldError::Unauthorized, "{:?}", ev),
+        (None, _, _, _) => {}
+    }
+    if let (Some(client), Some(kind)) = (sender, kind) {
+        if !kind.is_player(client) {
+            assert_eq!(error, WorldError::Unauthorized, "{:?}", ev);
+        }
+    }
+}
+
+/// Inventories never hold more stacks than they have slots, nor stacks above the item's maximum.
+fn check_inventories(world: &World) {
+    for (_, inventory) in world.query::<&Inventory>() {
+        assert!(inventory.items().count() <= inventory.slots());
+        for (item, count) in inventory.items() {
+            assert!(count <= item.kind().max_stack(), "{} {:?}", count, item);
+        }
+    }
+}
+
+fn serialize(world: &World) -> std::vec::Vec<u8> {
+    bincode::serialize(world).unwrap()
+}
+
+fn run(seed: u64) {
+    content::load(CONTENT_PATH).unwrap();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let start = World::default();
+    let mut world = start.clone();
+    let mut follow_ups = VecDeque::new();
+    let mut accepted = std::vec::Vec::new();
+    let mut max_id = 0;
+    // What each client is sent, and the world it makes of it.
+    let mut views: std::vec::Vec<_> = (0 .. CLIENTS).map(|client| {
+        let view = View::new(ClientId::from(client), &start);
+        let known = view.world().clone();
+        (view, known)
+    }).collect();
+    for _ in 0 .. STEPS {
+        let (sender, ev) = match follow_ups.pop_front() {
+            Some(ev) if rng.gen_range(0, 4) > 0 => (None, ev),
+            other => {
+                follow_ups.extend(other);
+                random_event(&mut rng, &world, max_id)
+            }
+        };
+        // The host gives new entities their ids.
+        let ev = match sender {
+            None => world.assign_id(ev, false),
+            Some(_) => ev,
+        };
+        match world.handle_event(sender, ev.clone()) {
+            Ok((next, evs)) => {
+                if let WorldEvent::SpawnEntity(..) = ev {
+                    max_id += 1;
+                }
+                world = next;
+                check_inventories(&world);
+                follow_ups.extend(evs);
+                for (view, known) in &mut views {
+                    let (forward, changes) = view.update(&world, sender, &ev);
+                    let sent = forward.then(|| (sender, ev.clone())).into_iter()
+                        .chain(changes.into_iter().map(|change| (None, change)));
+                    for (sender, ev) in sent {
+                        *known = known.handle_event(sender, ev.clone())
+                            .unwrap_or_else(|error| panic!("client rejected {:?}: {}", ev, error)).0;
+                    }
+                }
+                accepted.push((sender, ev));
+            }
+            Err(error) => check_error(&world, sender, &ev, error),
+        }
+    }
+    let mut replayed = start.clone();
+    for (sender, ev) in accepted {
+        replayed = replayed.handle_event(sender, ev).expect("accepted event rejected on replay").0;
+    }
+    assert_eq!(serialize(&replayed), serialize(&world));
+    assert_eq!(replayed.checksum(), world.checksum());
+    assert_ne!(start.checksum(), world.checksum());
+    for (client, (view, known)) in views.iter().enumerate() {
+        assert_eq!(known.checksum(), view.world().checksum());
+        // Clients know no more than their player can see.
+        let origin = world.player_entity(ClientId::from(client as u64)).and_then(|id| world.get::<Vec>(id));
+        for (id, _) in known.query::<&EntityKind>() {
+            let (origin, pos) = (origin.expect("clients without a player see nothing"), world.get::<Vec>(id).unwrap());
+            assert!(world.field_of_view(*origin, VIEW_RADIUS).contains(*pos), "{:?} is out of view", id);
+        }
+    }
+    // The spatial index is not sent along with the world, so it has to be rebuilt on load.
+    let loaded: World = bincode::deserialize(&serialize(&world)).unwrap();
+    for (id, pos) in world.query::<&Vec>() {
+        assert!(loaded.query_at::<&EntityKind>(*pos).any(|(other, _)| other == id));
+    }
+}
+
+#[test]
+fn provisional_ids_do_not_clash() {
+    content::load(CONTENT_PATH).unwrap();
+    let world = World::default();
+    let spawn = world.create_player_spawn_event(ClientId::from(0));
+    let predicted = world.assign_id(spawn.clone(), true);
+    let assigned = world.assign_id(spawn, false);
+    let (predicted_id, assigned_id) = match (&predicted, &assigned) {
+        (WorldEvent::SpawnEntity(a, _), WorldEvent::SpawnEntity(b, _)) => (*a, *b),
+        _ => panic!("creation events should become spawn events"),
+    };
+    assert!(predicted_id.is_provisional() && !assigned_id.is_provisional());
+    let (world, _) = world.handle_event(None, predicted).unwrap();
+    let (world, _) = world.handle_event(None, assigned).unwrap();
+    assert!(world.get::<EntityKind>(predicted_id).is_some() && world.get::<EntityKind>(assigned_id).is_some());
+    let again = world.create_player_spawn_event(ClientId::from(1));
+    assert_eq!(world.handle_event(None, again).unwrap_err(), WorldError::InvalidState);
+}
+
+#[test]
+fn random_events_seed_1() {
+    run(1);
+}
+
+#[test]
+fn random_events_seed_2() {
+    run(2);
+}
+
+#[test]
+fn random_events_seed_3() {
+    run(3);
+}
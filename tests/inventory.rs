@@ -0,0 +1,78 @@
+//! Inventory limits: stack sizes, slots, and actions that would change nothing.
+
+mod common;
+
+use rust_game::ClientId;
+use rust_game::content::{self, CONTENT_PATH};
+use rust_game::geom::Vec;
+use rust_game::world::{World, WorldEvent, WorldError, Entity, EntityKind, Inventory, PlayerActionEvent, PLAYER_SLOTS};
+use common::{spawn, spawn_player, stacks, item};
+
+#[test]
+fn pickups_stop_at_stack_and_slot_limits() {
+    content::load(CONTENT_PATH).unwrap();
+    let client = ClientId::from(0);
+    let pos = Vec::new(0, 0);
+    let clubs = [("wooden club", 1); PLAYER_SLOTS - 2];
+    let (world, id) = spawn_player(&World::default(), client, pos, &[&clubs[..], &[("log", 10)]].concat());
+    let mut pile = Inventory::new(2, None);
+    assert!(pile.insert_stack(&item("log"), 30));
+    let (world, treasure) = spawn(&world, Entity::new(pos, EntityKind::Treasure).with(pile));
+
+    // The player's stack of logs fills up to 16, the last free slot takes another 16, and 8 are left behind.
+    let (world, evs) = world.handle_event(None, WorldEvent::Enter(id, pos)).unwrap();
+    let logs = stacks(&world, id).into_iter().filter(|(name, _)| *name == "log").map(|(_, count)| count).collect::<std::vec::Vec<_>>();
+    assert_eq!(logs, [16, 16]);
+    assert_eq!(world.get::<Inventory>(id).unwrap().items().count(), PLAYER_SLOTS);
+    assert_eq!(world.get::<Inventory>(treasure).unwrap().count(), 8);
+    assert!(evs.iter().any(|ev| matches!(ev, WorldEvent::Notify(who, _) if *who == id)));
+}
+
+#[test]
+fn splitting_and_merging_respect_stack_limits() {
+    content::load(CONTENT_PATH).unwrap();
+    let client = ClientId::from(0);
+    let (world, id) = spawn_player(&World::default(), client, Vec::new(0, 0), &[("log", 10), ("wooden club", 1)]);
+    let act = |world: &World, action| world.handle_event(Some(client), WorldEvent::PlayerAction(id, action));
+
+    assert_eq!(act(&world, PlayerActionEvent::Split(0, 10)).unwrap_err(), WorldError::InvalidState);
+    assert_eq!(act(&world, PlayerActionEvent::Split(0, 0)).unwrap_err(), WorldError::InvalidState);
+    let (world, _) = act(&world, PlayerActionEvent::Split(0, 4)).unwrap();
+    assert_eq!(stacks(&world, id), [("log", 6), ("wooden club", 1), ("log", 4)]);
+
+    assert_eq!(act(&world, PlayerActionEvent::Merge(1, 0)).unwrap_err(), WorldError::InvalidState);
+    assert_eq!(act(&world, PlayerActionEvent::Merge(0, 0)).unwrap_err(), WorldError::InvalidState);
+    assert_eq!(act(&world, PlayerActionEvent::Merge(3, 0)).unwrap_err(), WorldError::OutOfBounds);
+    let (world, _) = act(&world, PlayerActionEvent::Merge(2, 0)).unwrap();
+    assert_eq!(stacks(&world, id), [("log", 10), ("wooden club", 1)]);
+
+    // Only what fits under the maximum stack size is merged, and a full stack takes nothing.
+    let (world, id) = spawn_player(&World::default(), client, Vec::new(0, 0), &[("log", 16), ("log", 5)]);
+    let act = |world: &World, action| world.handle_event(Some(client), WorldEvent::PlayerAction(id, action));
+    assert_eq!(act(&world, PlayerActionEvent::Merge(1, 0)).unwrap_err(), WorldError::InvalidState);
+    let (world, _) = act(&world, PlayerActionEvent::Split(0, 4)).unwrap();
+    let (world, _) = act(&world, PlayerActionEvent::Merge(1, 0)).unwrap();
+    assert_eq!(stacks(&world, id), [("log", 16), ("log", 1), ("log", 4)]);
+}
+
+#[test]
+fn splitting_needs_a_free_slot() {
+    content::load(CONTENT_PATH).unwrap();
+    let client = ClientId::from(0);
+    let full = [&[("log", 10)][..], &[("wooden club", 1); PLAYER_SLOTS - 1]].concat();
+    let (world, id) = spawn_player(&World::default(), client, Vec::new(0, 0), &full);
+    let (next, evs) = world.handle_event(Some(client), WorldEvent::PlayerAction(id, PlayerActionEvent::Split(0, 4))).unwrap();
+    assert_eq!(stacks(&next, id), stacks(&world, id));
+    assert!(evs.iter().any(|ev| matches!(ev, WorldEvent::Notify(who, _) if *who == id)));
+}
+
+#[test]
+fn only_food_can_be_eaten() {
+    content::load(CONTENT_PATH).unwrap();
+    let client = ClientId::from(0);
+    let (world, id) = spawn_player(&World::default(), client, Vec::new(0, 0), &[("log", 1), ("meat", 2)]);
+    let act = |world: &World, action| world.handle_event(Some(client), WorldEvent::PlayerAction(id, action));
+    assert_eq!(act(&world, PlayerActionEvent::Eat(0)).unwrap_err(), WorldError::InvalidState);
+    let (world, _) = act(&world, PlayerActionEvent::Eat(1)).unwrap();
+    assert_eq!(stacks(&world, id), [("log", 1), ("meat", 1)]);
+}
@@ -0,0 +1,56 @@
+//! Paths found by `find_path` can be walked with ordinary moves, including through entrances.
+
+mod common;
+
+use rust_game::ClientId;
+use rust_game::content::{self, CONTENT_PATH};
+use rust_game::geom::{Vec, Dir};
+use rust_game::world::{WorldEvent, Entity, PlayerActionEvent};
+use rust_game::world::pathfinding::PathOptions;
+use common::{spawn, generated_world};
+
+#[test]
+fn paths_lead_through_entrances() {
+    content::load(CONTENT_PATH).unwrap();
+    let world = generated_world();
+    let client = ClientId::from(0);
+    let free = |pos: Vec| world.tiles.get(pos).is_free() && world.linked_entrance(pos).is_none();
+    // Walk from beside an entrance on the surface to beside the entrance it leads to.
+    let (from, to) = (-60 .. 60).flat_map(|x| (-60 .. 60).map(move |y| Vec::new(x, y)))
+        .filter_map(|pos| world.linked_entrance(pos).map(|other| (pos, other)))
+        .find_map(|(pos, other)| {
+            let near = |pos: Vec| Dir::all().iter().map(move |dir| pos + dir.to_vec()).find(|pos| free(*pos));
+            Some((near(pos)?, near(other)?))
+        })
+        .expect("no entrance near the start");
+    assert_ne!(from.z, to.z);
+    let path = world.find_path(from, to, PathOptions::default()).expect("no path through the entrance");
+    let (mut world, id) = spawn(&world, Entity::player(client, from));
+    for dir in path {
+        world = world.apply(Some(client), WorldEvent::PlayerAction(id, PlayerActionEvent::Move(dir)), false).unwrap();
+    }
+    assert_eq!(world.get::<Vec>(id), Some(&to));
+}
+
+#[test]
+fn paths_to_an_entrance_end_where_it_leads() {
+    content::load(CONTENT_PATH).unwrap();
+    let world = generated_world();
+    let client = ClientId::from(0);
+    let free = |pos: Vec| world.tiles.get(pos).is_free() && world.linked_entrance(pos).is_none();
+    // Start two steps away from an entrance on the surface, with nothing in between.
+    let (from, entrance, other) = (-60 .. 60).flat_map(|x| (-60 .. 60).map(move |y| Vec::new(x, y)))
+        .filter_map(|pos| world.linked_entrance(pos).map(|other| (pos, other)))
+        .find_map(|(pos, other)| Dir::all().iter()
+            .find(|dir| free(pos + dir.to_vec()) && free(pos + dir.to_vec() + dir.to_vec()))
+            .map(|dir| (pos + dir.to_vec() + dir.to_vec(), pos, other)))
+        .expect("no entrance near the start");
+    let path = world.find_path(from, entrance, PathOptions::default()).expect("no path to the entrance");
+    let (mut world, id) = spawn(&world, Entity::player(client, from));
+    for dir in path {
+        world = world.apply(Some(client), WorldEvent::PlayerAction(id, PlayerActionEvent::Move(dir)), false).unwrap();
+    }
+    assert_eq!(world.get::<Vec>(id), Some(&other));
+    // Having been led through, the entity has nowhere further to go.
+    assert_eq!(world.find_path(other, entrance, PathOptions::default()), Some(vec![]));
+}
@@ -0,0 +1,87 @@
+//! Trades between two players: who may do what, and that the offers change hands together or not at all.
+
+mod common;
+
+use rust_game::ClientId;
+use rust_game::content::{self, CONTENT_PATH};
+use rust_game::geom::{Vec, Dir};
+use rust_game::world::{World, WorldEvent, WorldError, EntityId, PlayerActionEvent};
+use common::{spawn_player, stacks};
+
+fn act(world: &World, client: ClientId, id: EntityId, action: PlayerActionEvent) -> Result<World, WorldError> {
+    world.apply(Some(client), WorldEvent::PlayerAction(id, action), false)
+}
+
+#[test]
+fn trades_need_both_players_and_end_on_movement() {
+    content::load(CONTENT_PATH).unwrap();
+    let (a, b) = (ClientId::from(0), ClientId::from(1));
+    let mut world = World::default();
+    for client in &[a, b] {
+        world = world.apply(None, world.create_player_spawn_event(*client), false).unwrap();
+    }
+    let (id_a, id_b) = (world.player_entity(a).unwrap(), world.player_entity(b).unwrap());
+    // Both players start on the same tile, so one steps aside.
+    let dir = *Dir::all().iter()
+        .find(|dir| act(&world, b, id_b, PlayerActionEvent::Move(**dir)).unwrap().get::<Vec>(id_b) != world.get::<Vec>(id_b))
+        .expect("no free tile next to the spawn point");
+    world = act(&world, b, id_b, PlayerActionEvent::Move(dir)).unwrap();
+    let back = Dir::all().iter().copied().find(|other| other.to_vec() + dir.to_vec() == Vec::new(0, 0)).unwrap();
+
+    assert_eq!(act(&world, a, id_a, PlayerActionEvent::ConfirmTrade).unwrap_err(), WorldError::InvalidState);
+    world = act(&world, a, id_a, PlayerActionEvent::Trade(dir)).unwrap();
+    assert!(!world.trade_of(id_b).unwrap().open);
+    assert_eq!(act(&world, a, id_a, PlayerActionEvent::ConfirmTrade).unwrap_err(), WorldError::InvalidState);
+    world = act(&world, b, id_b, PlayerActionEvent::Trade(back)).unwrap();
+    assert!(world.trade_of(id_a).unwrap().open);
+    assert_eq!(act(&world, b, id_b, PlayerActionEvent::Offer(0, 1)).unwrap_err(), WorldError::OutOfBounds);
+
+    world = act(&world, a, id_a, PlayerActionEvent::ConfirmTrade).unwrap();
+    assert_eq!(world.trade_of(id_b).unwrap().confirmed, [true, false]);
+    world = act(&world, b, id_b, PlayerActionEvent::ConfirmTrade).unwrap();
+    assert!(world.trade_of(id_a).is_none());
+
+    world = act(&world, a, id_a, PlayerActionEvent::Trade(dir)).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Trade(back)).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Move(dir)).unwrap();
+    assert!(world.trade_of(id_a).is_none());
+}
+
+#[test]
+fn trades_happen_whole_or_not_at_all() {
+    content::load(CONTENT_PATH).unwrap();
+    let (a, b) = (ClientId::from(0), ClientId::from(1));
+    let world = World::default();
+    let (world, id_a) = spawn_player(&world, a, Vec::new(0, 0), &[("plank", 5)]);
+    // The second player's slots are all taken, even after offering some leather.
+    let full = [&[("leather", 3)][..], &[("wooden club", 1); 8]].concat();
+    let (mut world, id_b) = spawn_player(&world, b, Vec::new(1, 0), &full);
+    world = act(&world, a, id_a, PlayerActionEvent::Trade(Dir::right())).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Trade(Dir::left())).unwrap();
+    world = act(&world, a, id_a, PlayerActionEvent::Offer(0, 5)).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Offer(0, 1)).unwrap();
+    let (before_a, before_b) = (stacks(&world, id_a), stacks(&world, id_b));
+
+    // The leather would fit, but the planks would not, so neither changes hands.
+    world = act(&world, a, id_a, PlayerActionEvent::ConfirmTrade).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::ConfirmTrade).unwrap();
+    assert_eq!(stacks(&world, id_a), before_a);
+    assert_eq!(stacks(&world, id_b), before_b);
+    let trade = world.trade_of(id_a).expect("the trade should stay open");
+    assert_eq!(trade.confirmed, [false, false]);
+    assert_eq!(trade.offers[0].count() + trade.offers[1].count(), 6);
+
+    // Once the second player has room, everything is swapped at once.
+    world = act(&world, b, id_b, PlayerActionEvent::CancelTrade).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Drop(1, 1)).unwrap();
+    world = act(&world, a, id_a, PlayerActionEvent::Trade(Dir::right())).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Trade(Dir::left())).unwrap();
+    world = act(&world, a, id_a, PlayerActionEvent::Offer(0, 5)).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::Offer(0, 1)).unwrap();
+    world = act(&world, a, id_a, PlayerActionEvent::ConfirmTrade).unwrap();
+    world = act(&world, b, id_b, PlayerActionEvent::ConfirmTrade).unwrap();
+    assert!(world.trade_of(id_a).is_none());
+    assert_eq!(stacks(&world, id_a), [("leather", 1)]);
+    assert!(stacks(&world, id_b).contains(&("plank", 5)));
+    assert!(stacks(&world, id_b).contains(&("leather", 2)));
+}